@@ -1,32 +1,41 @@
 use crate::{
-    AppState, AudioChannels, Collider2D, CollisionEvent, CollisionLayer, CollisionMask, Fire,
-    NoWrapProtection, Score, Shape2D, SoundEffects, Velocity, Wrap, WrapCamera, AMMO, OBSTACLE,
-    PLAYER, SCORE_BIG_ASTEROID, SCORE_SAUCER, SCORE_SMALL_ASTEROID, SCORE_TINY_ASTEROID,
+    fragment, recycle_or_despawn, wrapped_offset, AppState, AudioChannels, CameraEffect,
+    Collider2D, CollisionEvent, CollisionLayer, CollisionMask, Damage, FragmentationTable,
+    GameClock, GameMode, Ghost, Health, Impact, MissileBlast, NoWrapProtection, Outline,
+    ParticleEmitter, PlayerControlled, Pool, PoolPlugin, Poolable, Recycle, SaucerRules, Score,
+    Shape2D, SoundEffects, SpawnMode, Velocity, Wrap, WrapCamera, Wrapped, AMMO, OBSTACLE, PLAYER,
+    SCORE_BIG_ASTEROID, SCORE_SAUCER, SCORE_SMALL_ASTEROID, SCORE_SMALL_SAUCER,
+    SCORE_TINY_ASTEROID,
 };
 use rand::prelude::*;
 use std::{collections::HashSet, time::Duration};
 
 use bevy::{
-    app::{AppBuilder, EventReader, Plugin},
+    app::{AppBuilder, EventReader, EventWriter, Plugin},
     asset::{AssetServer, Assets, Handle},
     core::{Time, Timer},
+    diagnostic::DiagnosticId,
     ecs::{
         entity::Entity,
-        query::{Or, With},
+        query::{Or, With, Without},
         schedule::SystemSet,
         system::{Commands, IntoSystem, Query, Res, ResMut},
+        world::EntityMut,
     },
     math::Size,
     math::{Vec2, Vec3},
-    render::{camera::OrthographicProjection, color::Color},
+    render::{camera::OrthographicProjection, color::Color, mesh::Mesh},
     sprite::{
         entity::{SpriteBundle, SpriteSheetBundle},
-        ColorMaterial, Sprite, TextureAtlas, TextureAtlasSprite,
+        ColorMaterial, Sprite, TextureAtlas,
     },
     transform::components::Transform,
 };
 use bevy_kira_audio::Audio;
 
+/// Time step of the trajectory predictions
+const SAFETY_STEP: f32 = 0.05;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u16)]
 pub enum Asteroid {
@@ -34,46 +43,181 @@ pub enum Asteroid {
     Small = SCORE_SMALL_ASTEROID,
     Tiny = SCORE_TINY_ASTEROID,
     Saucer = SCORE_SAUCER,
+    SmallSaucer = SCORE_SMALL_SAUCER,
+}
+
+impl Asteroid {
+    pub fn is_saucer(self) -> bool {
+        matches!(self, Asteroid::Saucer | Asteroid::SmallSaucer)
+    }
+}
+
+/// What a rock is made of. Saucers have no material.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AsteroidMaterial {
+    Rock,
+    /// Takes several hits, sparking on each of them
+    Metallic,
+    /// Detonates when destroyed, damaging its neighbors
+    Explosive,
+    /// Shatters into more, faster fragments
+    Icy,
+}
+
+impl AsteroidMaterial {
+    pub const ALL: [AsteroidMaterial; 4] = [
+        AsteroidMaterial::Rock,
+        AsteroidMaterial::Metallic,
+        AsteroidMaterial::Explosive,
+        AsteroidMaterial::Icy,
+    ];
+
+    pub fn random(rng: &mut impl Rng) -> Self {
+        *Self::ALL
+            .choose_weighted(rng, |material| match material {
+                AsteroidMaterial::Rock => 7,
+                _ => 1,
+            })
+            .unwrap()
+    }
+
+    pub fn score_multiplier(self) -> u16 {
+        match self {
+            AsteroidMaterial::Rock => 1,
+            AsteroidMaterial::Metallic => 3,
+            AsteroidMaterial::Explosive | AsteroidMaterial::Icy => 2,
+        }
+    }
+
+    /// Hits needed to destroy an asteroid, if more than one
+    pub fn hit_points(self, asteroid: Asteroid) -> Option<u16> {
+        match (self, asteroid) {
+            (AsteroidMaterial::Metallic, Asteroid::Big) => Some(4),
+            (AsteroidMaterial::Metallic, Asteroid::Small) => Some(3),
+            (AsteroidMaterial::Metallic, Asteroid::Tiny) => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Radius within which neighbors are damaged on destruction
+    pub fn blast_radius(self, asteroid: Asteroid) -> Option<f32> {
+        match self {
+            AsteroidMaterial::Explosive => Some(96.0 * asteroid_scale(asteroid)),
+            _ => None,
+        }
+    }
+
+    /// Extra fragments and speed factor of the pieces
+    pub fn shattering(self) -> (usize, f32) {
+        match self {
+            AsteroidMaterial::Icy => (2, 1.6),
+            _ => (0, 1.0),
+        }
+    }
+}
+
+/// Rocks are pooled, saucers are not
+impl Poolable for Asteroid {
+    const NAME: &'static str = "asteroid";
+    const SIZE: DiagnosticId = DiagnosticId::from_u128(0xe3b05a19_7c4f_4d82_a6f1_0d9c3e52b7a8);
+    const MISSES: DiagnosticId = DiagnosticId::from_u128(0x58d2c6fa_1e07_4b39_92c4_7af1b0e8d365);
+
+    fn reset(entity: &mut EntityMut) {
+        entity.remove::<Asteroid>();
+        entity.remove::<Spawn>();
+        entity.remove::<SpawnRadius>();
+        entity.remove::<Velocity>();
+        entity.remove::<Collider2D>();
+        entity.remove::<CollisionLayer>();
+        entity.remove::<CollisionMask>();
+        entity.remove::<Damage>();
+        entity.remove::<Health>();
+        entity.remove::<Outline>();
+        entity.remove::<AsteroidMaterial>();
+        entity.remove::<Wrap>();
+        entity.remove::<Wrapped>();
+    }
+}
+
+/// Emitted each time an asteroid or a saucer is destroyed
+pub struct AsteroidDestroyed {
+    pub asteroid: Asteroid,
+    pub position: Vec2,
+    pub velocity: Vec2,
 }
 
 struct SpawnTexture(Handle<TextureAtlas>);
 struct SpawnTimer(Timer, bool);
 struct SaucerTimer(Timer);
-struct ParticleColors(Vec<Handle<ColorMaterial>>);
-struct SaucerParticleColors(Vec<Handle<ColorMaterial>>);
+/// Outline and particle colors of a material
+struct Palette {
+    outline: Handle<ColorMaterial>,
+    particles: Vec<Color>,
+}
+
+/// One palette per material, in the `AsteroidMaterial::ALL` order
+struct Palettes(Vec<Palette>);
 
+impl Palettes {
+    fn get(&self, material: AsteroidMaterial) -> &Palette {
+        &self.0[material as usize]
+    }
+}
+
+/// Detonation of an explosive asteroid, damaging obstacles on the next frame
+struct Blast {
+    radius: f32,
+}
+struct SaucerParticleColors(Vec<Color>);
+
+/// Asteroid to be spawned on the next frame
 #[derive(Debug)]
-struct Spawn {
-    asteroid: Asteroid,
-    position: Vec2,
-    velocity: Vec2,
-    spin: f32,
+pub struct Spawn {
+    pub asteroid: Asteroid,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub spin: f32,
+    /// Shape of the asteroid, generated when missing
+    pub outline: Option<Outline>,
+    /// Picked at random when missing
+    pub material: Option<AsteroidMaterial>,
 }
 
 fn asteroid_scale(asteroid: Asteroid) -> f32 {
     match asteroid {
         Asteroid::Big => 1.0,
         Asteroid::Saucer => 0.75,
+        Asteroid::SmallSaucer => 0.5,
         Asteroid::Small => 0.5,
         Asteroid::Tiny => 0.25,
     }
 }
 
+/// Damage dealt to a ship hull on collision
+fn asteroid_damage(asteroid: Asteroid) -> u16 {
+    match asteroid {
+        Asteroid::Big => 3,
+        Asteroid::Saucer | Asteroid::SmallSaucer => 2,
+        Asteroid::Small => 2,
+        Asteroid::Tiny => 1,
+    }
+}
+
 fn spawn(
     mut commands: Commands,
     texture_atlas: Res<SpawnTexture>,
-    q_spawn: Query<(Entity, &Spawn)>,
+    palettes: Res<Palettes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_spawn: Query<(Entity, &Spawn, Option<&Handle<Mesh>>)>,
     audio: Res<Audio>,
     fx: Res<SoundEffects>,
     audio_channels: Res<AudioChannels>,
 ) {
-    for (entity, spawn) in q_spawn.iter() {
+    for (entity, spawn, mesh) in q_spawn.iter() {
         let mut rng = thread_rng();
         let scale = asteroid_scale(spawn.asteroid);
 
-        let transform =
-            Transform::from_translation(Vec3::new(spawn.position.x, spawn.position.y, 10.0))
-                * Transform::from_scale(Vec3::new(scale, scale, 1.0));
+        let translation = Vec3::new(spawn.position.x, spawn.position.y, 10.0);
 
         let mut e = commands.entity(entity);
         e.remove::<Spawn>()
@@ -81,65 +225,191 @@ fn spawn(
                 Vec2::new(spawn.velocity.x, spawn.velocity.y),
                 spawn.spin,
             ))
-            .insert(Collider2D {
-                shape: Shape2D::Circle(32.0 * scale),
-                ..Default::default()
-            })
             .insert(CollisionLayer(OBSTACLE))
             .insert(CollisionMask(PLAYER | AMMO))
             .insert(spawn.asteroid)
+            .insert(Damage(asteroid_damage(spawn.asteroid)));
+
+        if spawn.asteroid.is_saucer() {
+            e.insert(Collider2D {
+                shape: Shape2D::Circle(32.0 * scale),
+                ..Default::default()
+            })
             .insert_bundle(SpriteSheetBundle {
                 texture_atlas: texture_atlas.0.clone(),
-                transform,
-                sprite: TextureAtlasSprite {
-                    index: if spawn.asteroid == Asteroid::Saucer {
-                        0
-                    } else {
-                        rng.gen_range(1..5)
-                    },
-                    ..Default::default()
-                },
+                transform: Transform::from_translation(translation)
+                    * Transform::from_scale(Vec3::new(scale, scale, 1.0)),
                 ..Default::default()
             });
-        if spawn.asteroid != Asteroid::Tiny && spawn.asteroid != Asteroid::Saucer {
+        } else {
+            // Rocks are line loops, in a square sprite large enough to hold
+            // them whatever their rotation
+            let outline = spawn
+                .outline
+                .clone()
+                .unwrap_or_else(|| Outline::generate(rng.gen(), 32.0 * scale));
+            let size = outline.radius() * 2.0;
+            let material = spawn
+                .material
+                .unwrap_or_else(|| AsteroidMaterial::random(&mut rng));
+            if let Some(hit_points) = material.hit_points(spawn.asteroid) {
+                e.insert(Health::new(hit_points));
+            }
+            // A rock out of the pool gets its new outline in the mesh it
+            // already has
+            let mesh = match mesh.filter(|mesh| meshes.get(*mesh).is_some()) {
+                Some(mesh) => {
+                    if let Some(reused) = meshes.get_mut(mesh) {
+                        *reused = outline.mesh(size);
+                    }
+                    mesh.clone()
+                }
+                None => meshes.add(outline.mesh(size)),
+            };
+            e.insert(Collider2D {
+                shape: Shape2D::Polygon(outline.points.clone()),
+                ..Default::default()
+            })
+            .insert_bundle(SpriteBundle {
+                mesh,
+                material: palettes.get(material).outline.clone(),
+                transform: Transform::from_translation(translation),
+                sprite: Sprite::new(Vec2::new(size, size)),
+                ..Default::default()
+            })
+            .insert(outline)
+            .insert(material);
+        }
+
+        if spawn.asteroid != Asteroid::Tiny && !spawn.asteroid.is_saucer() {
             e.insert(Wrap::default());
         }
-        if spawn.asteroid == Asteroid::Saucer {
+        if spawn.asteroid.is_saucer() {
             e.insert(NoWrapProtection);
             audio.play_looped_in_channel(fx.ufo.clone(), &audio_channels.fx_ufo);
         }
     }
 }
 
+/// Asteroid to be spawned around a point, heading towards another area
 #[derive(Debug)]
-struct SpawnRadius {
+pub struct SpawnRadius {
     asteroid: Asteroid,
     origin: (Vec2, Size<f32>),
     direction: (Vec2, Size<f32>),
 }
 
-fn spawn_radius(mut commands: Commands, q_spawn: Query<(Entity, &SpawnRadius)>) {
+/// Keeps asteroids coming from the screen edges from hitting the ship right
+/// after they appear
+pub struct SpawnSafety {
+    /// Seconds during which a new asteroid must not hit the ship
+    pub horizon: f32,
+    /// Distance kept between the asteroid and the ship hulls
+    pub margin: f32,
+    /// Trajectories tried each frame before waiting for the next one
+    pub attempts: usize,
+}
+
+impl Default for SpawnSafety {
+    fn default() -> Self {
+        SpawnSafety {
+            horizon: 3.0,
+            margin: 40.0,
+            attempts: 10,
+        }
+    }
+}
+
+impl SpawnSafety {
+    /// Whether an asteroid comes within `clearance` of the ship before the
+    /// horizon, both keeping their velocity. Distances are measured across
+    /// the screen edges.
+    pub fn threatens(
+        &self,
+        (position, velocity): (Vec2, Vec2),
+        (ship, ship_velocity): (Vec2, Vec2),
+        clearance: f32,
+        projection: &OrthographicProjection,
+    ) -> bool {
+        let size = Vec2::new(
+            projection.right - projection.left,
+            projection.top - projection.bottom,
+        );
+        let steps = (self.horizon / SAFETY_STEP).ceil() as usize;
+        (0..=steps).any(|step| {
+            let time = step as f32 * SAFETY_STEP;
+            let offset = position - ship + (velocity - ship_velocity) * time;
+            let offset = Vec2::new(offset.x.rem_euclid(size.x), offset.y.rem_euclid(size.y));
+            wrapped_offset(Vec2::ZERO, offset, projection).length() < clearance
+        })
+    }
+
+    /// Whether a new asteroid keeps clear of the ship, if there is one, the
+    /// clearance including the asteroid size and the ship hull
+    pub fn is_safe(
+        &self,
+        asteroid: Asteroid,
+        trajectory: (Vec2, Vec2),
+        ship: Option<(&Transform, &Velocity, &Collider2D)>,
+        projection: &OrthographicProjection,
+    ) -> bool {
+        match ship {
+            Some((transform, velocity, collider)) => !self.threatens(
+                trajectory,
+                (transform.translation.truncate(), velocity.translation),
+                32.0 * asteroid_scale(asteroid) + self.margin + collider.shape.bounding_radius(),
+                projection,
+            ),
+            None => true,
+        }
+    }
+}
+
+fn spawn_radius(
+    mut commands: Commands,
+    safety: Res<SpawnSafety>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_player: Query<(&Transform, &Velocity, &Collider2D), With<PlayerControlled>>,
+    q_spawn: Query<(Entity, &SpawnRadius)>,
+) {
     let mut rng = thread_rng();
+    let projection = q_projection.single().ok();
+    let ship = q_player.single().ok();
+
     for (entity, spawn) in q_spawn.iter() {
-        let position = {
-            let angle = thread_rng().gen_range(0.0..std::f32::consts::PI * 2.0);
-            Vec2::new(
-                spawn.origin.0.x + angle.cos() * spawn.origin.1.width,
-                spawn.origin.0.y + angle.sin() * spawn.origin.1.height,
-            )
-        };
+        let trajectory = (0..safety.attempts.max(1))
+            .map(|_| {
+                let position = {
+                    let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+                    Vec2::new(
+                        spawn.origin.0.x + angle.cos() * spawn.origin.1.width,
+                        spawn.origin.0.y + angle.sin() * spawn.origin.1.height,
+                    )
+                };
 
-        let velocity = {
-            let direction_position = {
-                let angle = thread_rng().gen_range(0.0..std::f32::consts::PI * 2.0);
-                Vec2::new(
-                    spawn.direction.0.x + angle.cos() * spawn.direction.1.width,
-                    spawn.direction.0.y + angle.sin() * spawn.direction.1.height,
-                )
-            };
+                let velocity = {
+                    let direction_position = {
+                        let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+                        Vec2::new(
+                            spawn.direction.0.x + angle.cos() * spawn.direction.1.width,
+                            spawn.direction.0.y + angle.sin() * spawn.direction.1.height,
+                        )
+                    };
 
-            let speed = thread_rng().gen_range(50_f32..150_f32);
-            (direction_position - position).normalize() * speed
+                    let speed = rng.gen_range(50_f32..150_f32);
+                    (direction_position - position).normalize() * speed
+                };
+                (position, velocity)
+            })
+            .find(|&trajectory| match projection {
+                Some(projection) => safety.is_safe(spawn.asteroid, trajectory, ship, projection),
+                None => true,
+            });
+        // Without a safe trajectory, the asteroid is tried again on the next
+        // frame
+        let (position, velocity) = match trajectory {
+            Some(trajectory) => trajectory,
+            None => continue,
         };
 
         commands
@@ -150,28 +420,35 @@ fn spawn_radius(mut commands: Commands, q_spawn: Query<(Entity, &SpawnRadius)>)
                 velocity,
                 asteroid: spawn.asteroid,
                 spin: rng.gen_range(-5.0_f32..5.0_f32),
+                outline: None,
+                material: None,
             });
     }
 }
 
+/// Spawns big asteroids at random intervals, shortened by the game mode
 fn timed_spawn(
     mut commands: Commands,
+    mut pool: ResMut<Pool<Asteroid>>,
+    mode: Res<GameMode>,
+    clock: Res<GameClock>,
     time: Res<Time>,
     q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
     mut timer: ResMut<SpawnTimer>,
 ) {
     let mut rng = thread_rng();
     if timer.1 && timer.0.tick(time.delta()).just_finished() {
-        timer
-            .0
-            .set_duration(Duration::from_secs(rng.gen_range(1..5)));
+        timer.0.set_duration(Duration::from_secs_f32(
+            rng.gen_range(1.0..5.0) / mode.spawn_rate(clock.elapsed),
+        ));
         if let Ok(projection) = q_projection.single() {
             let diameter = Size::new(
                 projection.right - projection.left,
                 projection.top - projection.bottom,
             );
 
-            commands.spawn().insert(SpawnRadius {
+            let asteroid = pool.acquire(&mut commands);
+            commands.entity(asteroid).insert(SpawnRadius {
                 asteroid: Asteroid::Big,
                 origin: (Default::default(), diameter),
                 direction: (Default::default(), diameter / 2.0),
@@ -185,6 +462,8 @@ fn saucer_timed_spawn(
     time: Res<Time>,
     q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
     mut timer: ResMut<SaucerTimer>,
+    score: Res<Score>,
+    rules: Res<SaucerRules>,
 ) {
     let mut rng = thread_rng();
     if timer.0.tick(time.delta()).just_finished() {
@@ -192,6 +471,11 @@ fn saucer_timed_spawn(
             .0
             .set_duration(Duration::from_secs(15 + rng.gen_range(1..5)));
         if let Ok(projection) = q_projection.single() {
+            let asteroid = if rng.gen_bool(rules.small_saucer_chance(score.current)) {
+                Asteroid::SmallSaucer
+            } else {
+                Asteroid::Saucer
+            };
             let y = rng.gen_range(projection.bottom + 64.0..projection.top - 64.0);
             let (position, velocity, spin) = if rng.gen_bool(0.5) {
                 (
@@ -208,25 +492,43 @@ fn saucer_timed_spawn(
             };
 
             commands.spawn().insert(Spawn {
-                asteroid: Asteroid::Saucer,
+                asteroid,
                 position,
                 velocity,
                 spin,
+                outline: None,
+                material: None,
             });
         }
     }
 }
 
 /// On collision, an asteroid will despawn and, in place smaller asteroids will
-/// spawn.
+/// spawn, as defined by the `FragmentationTable`. Asteroids with `Health`
+/// only despawn once it is depleted, metallic ones sparking on each hit.
 fn destroy_on_collision(
     mut commands: Commands,
-    mut events: EventReader<CollisionEvent>,
+    (mut events, mut blasts): (EventReader<CollisionEvent>, EventReader<MissileBlast>),
+    mut destroyed: EventWriter<AsteroidDestroyed>,
     mut score: ResMut<Score>,
-    particle_colors: Res<ParticleColors>,
+    fragmentation: Res<FragmentationTable>,
+    palettes: Res<Palettes>,
     saucer_particle_colors: Res<SaucerParticleColors>,
-    q_asteroids: Query<(Entity, &Asteroid, &Transform, Option<&Velocity>)>,
+    mut rocks: ResMut<Pool<Asteroid>>,
+    mut emitters: ResMut<Pool<ParticleEmitter>>,
+    mut q_asteroids: Query<(
+        Entity,
+        &Asteroid,
+        &Transform,
+        Option<&Velocity>,
+        Option<&mut Health>,
+        Option<&Outline>,
+        Option<&AsteroidMaterial>,
+        Option<&Recycle>,
+    )>,
     q_collides_with: Query<(&Transform, Option<&Velocity>)>,
+    q_damage: Query<&Damage>,
+    q_ghosts: Query<&Ghost>,
     audio: Res<Audio>,
     fx: Res<SoundEffects>,
     audio_channels: Res<AudioChannels>,
@@ -235,15 +537,56 @@ fn destroy_on_collision(
     let mut already_done = HashSet::new();
     let mut rng = thread_rng();
 
-    for collision in events.iter() {
-        if let Ok((entity, asteroid, transform, velocity)) = q_asteroids.get(collision.source) {
+    let blasts = blasts.iter().map(MissileBlast::collision);
+    for collision in events.iter().copied().chain(blasts) {
+        if let Ok((entity, asteroid, transform, velocity, health, outline, material, recycle)) =
+            q_asteroids.get_mut(collision.source)
+        {
             if already_done.contains(&entity) {
                 continue;
             }
-            commands.entity(entity).despawn();
+
+            if let Some(mut health) = health {
+                let target = q_ghosts
+                    .get(collision.target)
+                    .map_or(collision.target, |ghost| ghost.target);
+                let damage = q_damage.get(target).map_or(1, |damage| damage.0);
+                already_done.insert(entity);
+                if !health.damage(damage) {
+                    if let Some(&AsteroidMaterial::Metallic) = material {
+                        let impact = match collision.contact {
+                            Some((position, _)) => position.extend(transform.translation.z),
+                            None => q_collides_with
+                                .get(collision.target)
+                                .map_or(transform.translation, |(target, _)| target.translation),
+                        };
+                        let velocity = velocity.map_or(Vec2::ZERO, |velocity| velocity.translation);
+                        let emitter = emitters.acquire(&mut commands);
+                        commands
+                            .entity(emitter)
+                            .insert(ParticleEmitter::sparks(
+                                palettes.get(AsteroidMaterial::Metallic).particles.clone(),
+                                velocity,
+                            ))
+                            .insert(Transform::from_translation(impact));
+                    }
+                    continue;
+                }
+            }
+
+            recycle_or_despawn(&mut commands, entity, recycle);
             already_done.insert(entity);
 
-            score.add(*asteroid as u16);
+            let material = material.copied();
+            let multiplier = material.map_or(1, AsteroidMaterial::score_multiplier);
+            score.add(u64::from(*asteroid as u16) * u64::from(multiplier));
+
+            if let Some(radius) = material.and_then(|material| material.blast_radius(*asteroid)) {
+                commands
+                    .spawn()
+                    .insert(Blast { radius })
+                    .insert(Transform::from_translation(transform.translation));
+            }
 
             let source_velocity = if let Some(&velocity) = velocity {
                 velocity
@@ -251,98 +594,128 @@ fn destroy_on_collision(
                 Default::default()
             };
 
-            if matches!(asteroid, Asteroid::Saucer) {
+            destroyed.send(AsteroidDestroyed {
+                asteroid: *asteroid,
+                position: transform.translation.truncate(),
+                velocity: source_velocity.translation,
+            });
+
+            if asteroid.is_saucer() {
                 audio.stop_channel(&audio_channels.fx_ufo);
             }
             audio.play_in_channel(fx.boom.clone(), &audio_channels.fx);
 
-            if let Some(asteroid) = match asteroid {
-                Asteroid::Big => Some(Asteroid::Small),
-                Asteroid::Small => Some(Asteroid::Tiny),
-                _ => None,
-            } {
-                let center = transform.translation.into();
-
-                let (target_position, target_velocity) =
-                    if let Ok((target_position, target_velocity)) =
-                        q_collides_with.get(collision.target)
-                    {
-                        (
-                            target_position.translation.into(),
-                            if let Some(&target_velocity) = target_velocity {
-                                target_velocity
-                            } else {
-                                Default::default()
-                            },
-                        )
-                    } else {
-                        (center, Default::default())
-                    };
-
-                let p = center + center - target_position
-                    + source_velocity.translation * 2.0
-                    + target_velocity.translation;
-
-                for _ in 0..rng.gen_range(2..5) {
-                    commands.spawn().insert(SpawnRadius {
-                        asteroid,
-                        origin: (center, Size::new(10.0, 10.0)),
-                        direction: (p, Size::new(100.0, 100.0)),
-                    });
+            if let Some(fragmentation) = fragmentation.get(*asteroid) {
+                let (hit_position, hit_velocity) = collision.contact.unwrap_or_else(|| {
+                    q_collides_with.get(collision.target).map_or(
+                        (transform.translation.truncate(), Vec2::ZERO),
+                        |(target, velocity)| {
+                            (
+                                target.translation.truncate(),
+                                velocity.map_or(Vec2::ZERO, |velocity| velocity.translation),
+                            )
+                        },
+                    )
+                });
+                let impact = Impact {
+                    position: transform.translation.truncate(),
+                    velocity: source_velocity.translation,
+                    rotation: transform.rotation,
+                    outline,
+                    material,
+                    hit_position,
+                    hit_velocity,
+                };
+                for spawn in fragment(fragmentation, &impact, &mut rng) {
+                    let piece = rocks.acquire(&mut commands);
+                    commands.entity(piece).insert(spawn);
                 }
             }
 
-            // Generating particles
-
-            let (count, radius, velocity_factor) = match asteroid {
-                Asteroid::Saucer => (500.0, 32.0, 50.0),
-                _ => {
-                    let scale = asteroid_scale(*asteroid);
-                    (200.0 * scale, 32.0 * scale, 1.0)
-                }
+            let emitter = if asteroid.is_saucer() {
+                ParticleEmitter::blast(
+                    saucer_particle_colors.0.clone(),
+                    source_velocity.translation,
+                    AMMO,
+                )
+            } else {
+                ParticleEmitter::debris(
+                    palettes
+                        .get(material.unwrap_or(AsteroidMaterial::Rock))
+                        .particles
+                        .clone(),
+                    asteroid_scale(*asteroid),
+                    source_velocity.translation,
+                )
             };
+            let entity = emitters.acquire(&mut commands);
+            commands
+                .entity(entity)
+                .insert(emitter)
+                .insert(Transform::from_translation(transform.translation));
+        }
+    }
+}
 
-            for _ in 0..count as u16 {
-                let size = {
-                    let size = rng.gen_range(1.0..3.0);
-                    Vec2::new(size, size)
-                };
-
-                let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
-                let far = rng.gen_range(0.0..radius);
-
-                let relative_position = Vec3::new(angle.cos() * far, angle.sin() * far, 0.0);
-
-                let velocity =
-                    source_velocity.translation + (relative_position * velocity_factor).into();
+/// Shakes the camera on destructions, saucers giving a zoom punch
+fn camera_feedback(
+    mut destroyed: EventReader<AsteroidDestroyed>,
+    mut effects: EventWriter<CameraEffect>,
+) {
+    for destroyed in destroyed.iter() {
+        let trauma = match destroyed.asteroid {
+            Asteroid::Big => 0.35,
+            Asteroid::Small => 0.2,
+            Asteroid::Tiny => 0.1,
+            Asteroid::Saucer | Asteroid::SmallSaucer => 0.4,
+        };
+        effects.send(CameraEffect::Shake(trauma));
+        if destroyed.asteroid.is_saucer() {
+            effects.send(CameraEffect::ZoomPunch(0.06));
+        }
+    }
+}
 
-                let mut e = commands.spawn_bundle(SpriteBundle {
-                    material: match asteroid {
-                        Asteroid::Saucer => saucer_particle_colors.0
-                            [rng.gen_range(0..saucer_particle_colors.0.len())]
-                        .clone(),
+/// Damages every obstacle within the blast radius, as if it was hit by the
+/// blast. Explosive asteroids caught in it detonate in turn.
+fn detonate(
+    mut commands: Commands,
+    mut events: EventWriter<CollisionEvent>,
+    mut effects: EventWriter<CameraEffect>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_blasts: Query<(Entity, &Blast, &Transform)>,
+    q_obstacles: Query<(Entity, &Transform, &Collider2D, &CollisionLayer), Without<Ghost>>,
+) {
+    let projection = match q_projection.single() {
+        Ok(projection) => projection,
+        Err(_) => return,
+    };
+
+    for (blast_entity, blast, transform) in q_blasts.iter() {
+        let center = transform.translation.truncate();
+        for (obstacle, obstacle_transform, collider, layer) in q_obstacles.iter() {
+            if layer.0 & OBSTACLE == 0 {
+                continue;
+            }
 
-                        _ => particle_colors.0[rng.gen_range(0..particle_colors.0.len())].clone(),
-                    },
-                    transform: Transform::from_translation(
-                        transform.translation + relative_position,
-                    ),
-                    sprite: Sprite::new(size),
-                    ..Default::default()
+            let distance = wrapped_offset(
+                center,
+                obstacle_transform.translation.truncate() + collider.position,
+                projection,
+            )
+            .length();
+
+            if distance < blast.radius + collider.shape.bounding_radius() {
+                events.send(CollisionEvent {
+                    source: obstacle,
+                    target: blast_entity,
+                    layer: AMMO,
+                    contact: None,
                 });
-                e.insert(Velocity::new(velocity, 0.0));
-
-                if asteroid == &Asteroid::Saucer {
-                    e.insert(Collider2D {
-                        shape: Shape2D::Rectangle(size),
-                        ..Default::default()
-                    })
-                    .insert(Fire)
-                    .insert(CollisionLayer(AMMO))
-                    .insert(CollisionMask(OBSTACLE));
-                }
             }
         }
+        effects.send(CameraEffect::Shake(0.5));
+        commands.entity(blast_entity).despawn();
     }
 }
 
@@ -361,44 +734,99 @@ fn prepare_resources(
         5,
     ))));
 
-    commands.insert_resource(ParticleColors(vec![
-        materials.add(Color::rgb(0.18, 0.18, 0.18).into()),
-        materials.add(Color::rgb(0.23, 0.20, 0.20).into()),
-        materials.add(Color::rgb(0.29, 0.26, 0.26).into()),
-        materials.add(Color::rgb(0.36, 0.29, 0.29).into()),
-        materials.add(Color::rgb(0.40, 0.32, 0.32).into()),
+    let mut palette = |outline: Color, particles: &[Color]| Palette {
+        outline: materials.add(outline.into()),
+        particles: particles.to_vec(),
+    };
+    commands.insert_resource(Palettes(vec![
+        // Rock
+        palette(
+            Color::rgb(0.85, 0.85, 0.8),
+            &[
+                Color::rgb(0.18, 0.18, 0.18),
+                Color::rgb(0.23, 0.20, 0.20),
+                Color::rgb(0.29, 0.26, 0.26),
+                Color::rgb(0.36, 0.29, 0.29),
+                Color::rgb(0.40, 0.32, 0.32),
+            ],
+        ),
+        // Metallic
+        palette(
+            Color::rgb(0.6, 0.7, 0.85),
+            &[
+                Color::rgb(1.0, 0.9, 0.5),
+                Color::rgb(1.0, 0.75, 0.3),
+                Color::rgb(0.75, 0.8, 0.9),
+                Color::rgb(0.5, 0.55, 0.65),
+            ],
+        ),
+        // Explosive
+        palette(
+            Color::rgb(1.0, 0.45, 0.2),
+            &[
+                Color::rgb(1.0, 0.9, 0.3),
+                Color::rgb(1.0, 0.55, 0.0),
+                Color::rgb(0.9, 0.2, 0.0),
+                Color::rgb(0.3, 0.1, 0.05),
+            ],
+        ),
+        // Icy
+        palette(
+            Color::rgb(0.6, 0.95, 1.0),
+            &[
+                Color::rgb(1.0, 1.0, 1.0),
+                Color::rgb(0.8, 0.95, 1.0),
+                Color::rgb(0.55, 0.8, 0.95),
+                Color::rgb(0.35, 0.6, 0.85),
+            ],
+        ),
     ]));
 
     commands.insert_resource(SaucerParticleColors(vec![
-        materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-        materials.add(Color::rgb(1.0, 0.35, 0.0).into()),
-        materials.add(Color::rgb(1.0, 1.0, 1.0).into()),
-        materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-        materials.add(Color::rgb(1.0, 1.0, 1.0).into()),
+        Color::rgb(1.0, 0.0, 0.0),
+        Color::rgb(1.0, 0.35, 0.0),
+        Color::rgb(1.0, 1.0, 1.0),
+        Color::rgb(1.0, 0.0, 0.0),
+        Color::rgb(1.0, 1.0, 1.0),
     ]));
 }
 
-fn enter(mut commands: Commands, query: Query<Entity, With<Asteroid>>) {
-    commands.insert_resource(SpawnTimer(Timer::from_seconds(1.0, true), true));
+fn enter(
+    mut commands: Commands,
+    mode: Res<SpawnMode>,
+    query: Query<(Entity, Option<&Recycle>), With<Asteroid>>,
+) {
+    // Waves spawn their own asteroids
+    commands.insert_resource(SpawnTimer(
+        Timer::from_seconds(1.0, true),
+        *mode == SpawnMode::Endless,
+    ));
     commands.insert_resource(SaucerTimer(Timer::from_seconds(10.0, true)));
 
-    for e in query.iter() {
-        commands.entity(e).despawn();
+    for (e, recycle) in query.iter() {
+        recycle_or_despawn(&mut commands, e, recycle);
     }
 }
 
-fn exit(mut commands: Commands, query: Query<Entity, Or<(With<Spawn>, With<SpawnRadius>)>>) {
+fn exit(
+    mut commands: Commands,
+    query: Query<(Entity, Option<&Recycle>), Or<(With<Spawn>, With<SpawnRadius>, With<Blast>)>>,
+) {
     commands.remove_resource::<SpawnTimer>();
     commands.remove_resource::<SaucerTimer>();
 
-    for e in query.iter() {
-        commands.entity(e).despawn();
+    for (e, recycle) in query.iter() {
+        recycle_or_despawn(&mut commands, e, recycle);
     }
 }
 
 impl Plugin for AsteroidsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(prepare_resources.system())
+        app.add_plugin(PoolPlugin::<Asteroid>::default())
+            .add_event::<AsteroidDestroyed>()
+            .init_resource::<FragmentationTable>()
+            .init_resource::<SpawnSafety>()
+            .add_startup_system(prepare_resources.system())
             .add_system_set(SystemSet::on_enter(AppState::Game).with_system(enter.system()))
             .add_system_set(SystemSet::on_exit(AppState::Game).with_system(exit.system()))
             .add_system_set(
@@ -407,7 +835,59 @@ impl Plugin for AsteroidsPlugin {
                     .with_system(saucer_timed_spawn.system())
                     .with_system(spawn.system())
                     .with_system(spawn_radius.system())
-                    .with_system(destroy_on_collision.system()),
+                    .with_system(destroy_on_collision.system())
+                    .with_system(detonate.system())
+                    .with_system(camera_feedback.system()),
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projection() -> OrthographicProjection {
+        OrthographicProjection {
+            left: -400.0,
+            right: 400.0,
+            bottom: -300.0,
+            top: 300.0,
+            ..Default::default()
+        }
+    }
+
+    fn threatens(position: Vec2, velocity: Vec2) -> bool {
+        SpawnSafety::default().threatens(
+            (position, velocity),
+            (Vec2::ZERO, Vec2::ZERO),
+            50.0,
+            &projection(),
+        )
+    }
+
+    #[test]
+    fn asteroids_heading_for_the_ship_threaten_it() {
+        assert!(threatens(Vec2::new(-300.0, 0.0), Vec2::new(150.0, 0.0)));
+        assert!(threatens(Vec2::new(30.0, 0.0), Vec2::ZERO));
+    }
+
+    #[test]
+    fn asteroids_passing_by_or_too_slow_do_not_threaten_the_ship() {
+        assert!(!threatens(Vec2::new(-300.0, 0.0), Vec2::new(-150.0, 0.0)));
+        assert!(!threatens(Vec2::new(-300.0, 100.0), Vec2::new(150.0, 0.0)));
+        assert!(!threatens(Vec2::new(-300.0, 0.0), Vec2::new(50.0, 0.0)));
+    }
+
+    #[test]
+    fn threats_are_seen_across_the_screen_edges() {
+        // Leaves on the right, comes back on the left
+        assert!(threatens(Vec2::new(300.0, 0.0), Vec2::new(200.0, 0.0)));
+        // The ship moving towards the asteroid counts too
+        assert!(SpawnSafety::default().threatens(
+            (Vec2::new(0.0, 250.0), Vec2::ZERO),
+            (Vec2::ZERO, Vec2::new(0.0, -150.0)),
+            50.0,
+            &projection(),
+        ));
+    }
+}
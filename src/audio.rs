@@ -4,6 +4,9 @@ use bevy::{
     ecs::system::{Commands, IntoSystem, Res},
 };
 
+const FX_VOLUME: f32 = 0.1;
+const FX_UFO_VOLUME: f32 = 2.0;
+
 use bevy_kira_audio::{Audio, AudioChannel, AudioSource};
 pub struct AudioPlugin;
 
@@ -11,26 +14,45 @@ pub struct AudioChannels {
     pub background: AudioChannel,
     pub fx: AudioChannel,
     pub fx_ufo: AudioChannel,
+    pub engine: AudioChannel,
+}
+
+/// Volumes set by the player, from 0 to 1
+pub struct Volumes {
+    pub music: f32,
+    pub effects: f32,
+}
+
+impl Default for Volumes {
+    fn default() -> Self {
+        Volumes {
+            music: 1.0,
+            effects: 1.0,
+        }
+    }
 }
 
 pub struct SoundEffects {
     pub fire: Handle<AudioSource>,
     pub ufo: Handle<AudioSource>,
     pub boom: Handle<AudioSource>,
+    pub life: Handle<AudioSource>,
+    pub engine: Handle<AudioSource>,
 }
 
 pub fn prepare_resources(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     audio: Res<Audio>,
+    volumes: Res<Volumes>,
 ) {
     let channels = AudioChannels {
         background: AudioChannel::new("music".to_owned()),
         fx: AudioChannel::new("fx".to_owned()),
         fx_ufo: AudioChannel::new("fx_ufo".to_owned()),
+        engine: AudioChannel::new("engine".to_owned()),
     };
-    audio.set_volume_in_channel(2.0, &channels.fx_ufo);
-    audio.set_volume_in_channel(0.1, &channels.fx);
+    set_volumes(&audio, &channels, &volumes);
 
     audio.play_looped_in_channel(
         asset_server.load("audio/background.mp3"),
@@ -41,12 +63,29 @@ pub fn prepare_resources(
         fire: asset_server.load("audio/fire.wav"),
         ufo: asset_server.load("audio/ufo.wav"),
         boom: asset_server.load("audio/boom.wav"),
+        life: asset_server.load("audio/life.wav"),
+        engine: asset_server.load("audio/engine.wav"),
     });
     commands.insert_resource(channels);
 }
 
+/// The engine volume follows the thrust, it is set by the thruster
+fn set_volumes(audio: &Audio, channels: &AudioChannels, volumes: &Volumes) {
+    audio.set_volume_in_channel(volumes.music, &channels.background);
+    audio.set_volume_in_channel(FX_VOLUME * volumes.effects, &channels.fx);
+    audio.set_volume_in_channel(FX_UFO_VOLUME * volumes.effects, &channels.fx_ufo);
+}
+
+fn apply_volumes(audio: Res<Audio>, channels: Res<AudioChannels>, volumes: Res<Volumes>) {
+    if volumes.is_changed() {
+        set_volumes(&audio, &channels, &volumes);
+    }
+}
+
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(prepare_resources.system());
+        app.init_resource::<Volumes>()
+            .add_startup_system(prepare_resources.system())
+            .add_system(apply_volumes.system());
     }
 }
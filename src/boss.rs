@@ -0,0 +1,668 @@
+/// Bosses
+/// Every few waves, a boss shows up instead of the asteroids. A boss is a
+/// `Boss` entity driving one or more `BossSegment`s, which are regular
+/// obstacles: they collide, move and wrap on their own, each with its own
+/// `Health`.
+///
+/// A boss goes through phases: it is invulnerable while `Arriving`, then
+/// `Attacking`, and `Enraged` once weakened. The mothership launches
+/// asteroids and saucers from its pods, its core being shielded until every
+/// pod is destroyed. The giant rock sheds fragments as it takes damage.
+use crate::{
+    wrapped_offset, AppState, AsteroidClass, AsteroidMaterial, AsteroidSpawn, AudioChannels,
+    CameraEffect, Collider2D, CollisionEvent, CollisionLayer, CollisionMask, Damage, Ghost, Health,
+    MissileBlast, NoWrapProtection, Outline, PlayerControlled, Score, Shape2D, SoundEffects,
+    SpawnSafety, Velocity, Wrap, WrapCamera, AMMO, OBSTACLE, PLAYER, SCORE_BOSS_CORE,
+    SCORE_BOSS_POD, SCORE_GIANT_ROCK,
+};
+use bevy::{
+    app::{AppBuilder, EventReader, EventWriter, Plugin},
+    asset::{Assets, Handle},
+    core::{Time, Timer},
+    ecs::{
+        entity::Entity,
+        query::{Or, With, Without},
+        schedule::SystemSet,
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    math::{Vec2, Vec3},
+    render::{camera::OrthographicProjection, color::Color, mesh::Mesh},
+    sprite::{entity::SpriteBundle, ColorMaterial, Sprite},
+    transform::components::Transform,
+};
+use bevy_kira_audio::Audio;
+use rand::prelude::*;
+use std::collections::HashSet;
+
+const ARRIVAL_DURATION: f32 = 3.0;
+const ARRIVAL_SPEED: f32 = 120.0;
+const ARRIVAL_MARGIN: f32 = 160.0;
+const DRIFT_SPEED: f32 = 40.0;
+const ENRAGED_FACTOR: f32 = 2.0;
+/// Hit points the giant rock loses between two sheddings
+const SHED_STEP: u16 = 4;
+const BAR_WIDTH: f32 = 400.0;
+const BAR_HEIGHT: f32 = 8.0;
+const BAR_MARGIN: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BossKind {
+    /// Core surrounded by pods launching asteroids and saucers
+    Mothership,
+    /// Huge asteroid shedding fragments as it takes damage
+    GiantRock,
+}
+
+impl BossKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BossKind::Mothership => "Mothership",
+            BossKind::GiantRock => "Giant rock",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BossPhase {
+    /// Moving into the screen, invulnerable
+    Arriving,
+    Attacking,
+    /// Weakened: faster and more aggressive
+    Enraged,
+}
+
+/// Which waves are boss waves
+pub struct BossRules {
+    pub every: u32,
+    /// Bosses met in turn
+    pub kinds: Vec<BossKind>,
+}
+
+impl Default for BossRules {
+    fn default() -> Self {
+        BossRules {
+            every: 5,
+            kinds: vec![BossKind::Mothership, BossKind::GiantRock],
+        }
+    }
+}
+
+impl BossRules {
+    pub fn boss_for(&self, wave: u32) -> Option<BossKind> {
+        // No boss without a period, nor before the first wave
+        if self.kinds.is_empty() || wave == 0 || wave.checked_rem(self.every) != Some(0) {
+            return None;
+        }
+        let index = (wave / self.every - 1) as usize % self.kinds.len();
+        Some(self.kinds[index])
+    }
+}
+
+/// Boss to be spawned on the next frame
+pub struct BossSpawn(pub BossKind);
+
+pub struct Boss {
+    pub kind: BossKind,
+    pub phase: BossPhase,
+    segments: Vec<Entity>,
+    max_health: u16,
+    /// Arrival, then launch period
+    timer: Timer,
+    /// Health at the last shedding
+    shed: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Core,
+    Pod,
+    Rock,
+}
+
+pub struct BossSegment {
+    pub boss: Entity,
+    role: Role,
+}
+
+/// Emitted when every segment of a boss has been destroyed
+pub struct BossDefeated {
+    pub kind: BossKind,
+}
+
+struct BossColors {
+    hull: Handle<ColorMaterial>,
+    shielded: Handle<ColorMaterial>,
+    rock: Handle<ColorMaterial>,
+    bar_back: Handle<ColorMaterial>,
+    bar_fill: Handle<ColorMaterial>,
+}
+
+#[derive(Clone, Copy)]
+enum HealthBar {
+    Back,
+    Fill,
+}
+
+fn launch_period(kind: BossKind, phase: BossPhase) -> f32 {
+    match (kind, phase) {
+        (BossKind::Mothership, BossPhase::Enraged) => 2.5,
+        (BossKind::Mothership, _) => 4.0,
+        (BossKind::GiantRock, BossPhase::Enraged) => 3.0,
+        (BossKind::GiantRock, _) => 6.0,
+    }
+}
+
+fn spawn_bosses(
+    mut commands: Commands,
+    colors: Res<BossColors>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_spawn: Query<(Entity, &BossSpawn)>,
+) {
+    let projection = match q_projection.single() {
+        Ok(projection) => projection,
+        Err(_) => return,
+    };
+    let mut rng = thread_rng();
+
+    for (entity, spawn) in q_spawn.iter() {
+        let kind = spawn.0;
+        let origin = Vec2::new(
+            projection.left - ARRIVAL_MARGIN,
+            rng.gen_range(projection.bottom / 2.0..projection.top / 2.0),
+        );
+        let velocity = Vec2::new(ARRIVAL_SPEED, 0.0);
+
+        let parts = match kind {
+            BossKind::Mothership => {
+                let mut parts = vec![(Role::Core, Vec2::ZERO, Outline::regular(8, 48.0), 20)];
+                for offset in [
+                    Vec2::new(-90.0, 0.0),
+                    Vec2::new(90.0, 0.0),
+                    Vec2::new(0.0, -76.0),
+                    Vec2::new(0.0, 76.0),
+                ]
+                .iter()
+                {
+                    parts.push((Role::Pod, *offset, Outline::regular(6, 24.0), 6));
+                }
+                parts
+            }
+            BossKind::GiantRock => vec![(
+                Role::Rock,
+                Vec2::ZERO,
+                Outline::generate(rng.gen(), 128.0),
+                40,
+            )],
+        };
+
+        let mut segments = Vec::new();
+        let mut max_health = 0;
+        for (role, offset, outline, health) in parts {
+            let size = outline.radius() * 2.0;
+            let (material, spin) = match role {
+                Role::Core => (colors.shielded.clone(), 0.0),
+                Role::Pod => (colors.hull.clone(), 0.0),
+                Role::Rock => (colors.rock.clone(), rng.gen_range(-0.5..0.5)),
+            };
+            let position = origin + offset;
+            let segment = commands
+                .spawn_bundle(SpriteBundle {
+                    mesh: meshes.add(outline.mesh(size)),
+                    material,
+                    transform: Transform::from_translation(position.extend(10.0)),
+                    sprite: Sprite::new(Vec2::new(size, size)),
+                    ..Default::default()
+                })
+                .insert(Collider2D {
+                    shape: Shape2D::Polygon(outline.points.clone()),
+                    ..Default::default()
+                })
+                .insert(CollisionLayer(OBSTACLE))
+                .insert(CollisionMask(PLAYER))
+                .insert(Velocity::new(velocity, spin))
+                .insert(Health::new(health))
+                .insert(Damage(3))
+                .insert(Wrap::default())
+                .insert(BossSegment { boss: entity, role })
+                .insert(outline)
+                .id();
+            segments.push(segment);
+            max_health += health;
+        }
+
+        commands.entity(entity).remove::<BossSpawn>().insert(Boss {
+            kind,
+            phase: BossPhase::Arriving,
+            segments,
+            max_health,
+            timer: Timer::from_seconds(ARRIVAL_DURATION, false),
+            shed: max_health,
+        });
+    }
+}
+
+/// Ammunition hitting a vulnerable segment damages it. Destroyed segments
+/// give points, a destroyed giant rock breaks into small asteroids.
+fn damage_bosses(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    mut blasts: EventReader<MissileBlast>,
+    mut score: ResMut<Score>,
+    mut q_segments: Query<(
+        Entity,
+        &BossSegment,
+        &Transform,
+        &CollisionMask,
+        &mut Health,
+    )>,
+    q_damage: Query<&Damage>,
+    q_ghosts: Query<&Ghost>,
+    audio: Res<Audio>,
+    fx: Res<SoundEffects>,
+    audio_channels: Res<AudioChannels>,
+) {
+    // Ensures each segment is hit once per frame
+    let mut already_done = HashSet::new();
+    let mut rng = thread_rng();
+
+    let blasts = blasts.iter().map(MissileBlast::collision);
+    for collision in events.iter().copied().chain(blasts) {
+        if collision.layer & AMMO == 0 {
+            continue;
+        }
+        if let Ok((entity, segment, transform, mask, mut health)) =
+            q_segments.get_mut(collision.source)
+        {
+            // Missile blasts are sent whatever the masks, shielded segments
+            // ignore them like any other ammunition
+            if mask.0 & AMMO == 0 || health.is_dead() || already_done.contains(&entity) {
+                continue;
+            }
+            already_done.insert(entity);
+
+            let target = q_ghosts
+                .get(collision.target)
+                .map_or(collision.target, |ghost| ghost.target);
+            let damage = q_damage.get(target).map_or(1, |damage| damage.0);
+            if !health.damage(damage) {
+                continue;
+            }
+
+            commands.entity(entity).despawn();
+            audio.play_in_channel(fx.boom.clone(), &audio_channels.fx);
+
+            score.add(u64::from(match segment.role {
+                Role::Core => SCORE_BOSS_CORE,
+                Role::Pod => SCORE_BOSS_POD,
+                Role::Rock => SCORE_GIANT_ROCK,
+            }));
+
+            if segment.role == Role::Rock {
+                let center = transform.translation.truncate();
+                for i in 0..6 {
+                    let angle = i as f32 * std::f32::consts::PI / 3.0 + rng.gen_range(-0.3..0.3);
+                    let direction = Vec2::new(angle.cos(), angle.sin());
+                    commands.spawn().insert(AsteroidSpawn {
+                        asteroid: AsteroidClass::Small,
+                        position: center + direction * 64.0,
+                        velocity: direction * rng.gen_range(80.0..160.0),
+                        spin: rng.gen_range(-5.0..5.0),
+                        outline: None,
+                        material: Some(AsteroidMaterial::Rock),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Runs the phase state machine of each boss, and its attacks
+fn update_bosses(
+    mut commands: Commands,
+    time: Res<Time>,
+    colors: Res<BossColors>,
+    mut defeated: EventWriter<BossDefeated>,
+    mut effects: EventWriter<CameraEffect>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    safety: Res<SpawnSafety>,
+    q_player: Query<(&Transform, &Velocity, &Collider2D), With<PlayerControlled>>,
+    mut q_bosses: Query<(Entity, &mut Boss)>,
+    mut q_segments: Query<
+        (
+            &BossSegment,
+            &Transform,
+            &Health,
+            &mut Velocity,
+            &mut CollisionMask,
+            &mut Handle<ColorMaterial>,
+        ),
+        Without<PlayerControlled>,
+    >,
+) {
+    let projection = match q_projection.single() {
+        Ok(projection) => projection,
+        Err(_) => return,
+    };
+    let ship = q_player.single().ok();
+    let player = ship.map(|(transform, ..)| transform.translation.truncate());
+    let mut rng = thread_rng();
+
+    for (entity, mut boss) in q_bosses.iter_mut() {
+        // Segments still alive, with their role, position and health
+        let alive: Vec<(Entity, Role, Vec2, u16)> = boss
+            .segments
+            .iter()
+            .filter_map(|segment| {
+                q_segments
+                    .get_mut(*segment)
+                    .ok()
+                    .filter(|(_, _, health, ..)| !health.is_dead())
+                    .map(|(part, transform, health, ..)| {
+                        (
+                            *segment,
+                            part.role,
+                            transform.translation.truncate(),
+                            health.current,
+                        )
+                    })
+            })
+            .collect();
+
+        if alive.is_empty() {
+            commands.entity(entity).despawn();
+            defeated.send(BossDefeated { kind: boss.kind });
+            effects.send(CameraEffect::Shake(1.0));
+            effects.send(CameraEffect::ZoomPunch(0.1));
+            continue;
+        }
+
+        let health: u16 = alive.iter().map(|(.., health)| health).sum();
+        let pods = alive
+            .iter()
+            .filter(|(_, role, ..)| *role == Role::Pod)
+            .count();
+
+        boss.timer.tick(time.delta());
+
+        // Phase transitions
+        let next = match boss.phase {
+            BossPhase::Arriving if boss.timer.finished() => Some(BossPhase::Attacking),
+            BossPhase::Attacking
+                if match boss.kind {
+                    BossKind::Mothership => pods == 0,
+                    BossKind::GiantRock => health * 2 <= boss.max_health,
+                } =>
+            {
+                Some(BossPhase::Enraged)
+            }
+            _ => None,
+        };
+
+        if let Some(phase) = next {
+            boss.phase = phase;
+            boss.timer = Timer::from_seconds(launch_period(boss.kind, phase), true);
+
+            let drift = if phase == BossPhase::Attacking {
+                let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+                Some(Vec2::new(angle.cos(), angle.sin()) * DRIFT_SPEED)
+            } else {
+                None
+            };
+
+            for (segment, ..) in alive.iter() {
+                if let Ok((part, _, _, mut velocity, mut mask, _)) = q_segments.get_mut(*segment) {
+                    match drift {
+                        Some(drift) => velocity.translation = drift,
+                        None => {
+                            velocity.translation *= ENRAGED_FACTOR;
+                            velocity.rotation *= ENRAGED_FACTOR;
+                        }
+                    }
+
+                    // The core is shielded as long as pods remain
+                    if part.role != Role::Core {
+                        mask.0 = PLAYER | AMMO;
+                    }
+                }
+            }
+        }
+
+        if boss.phase == BossPhase::Arriving {
+            continue;
+        }
+
+        match boss.kind {
+            BossKind::Mothership => {
+                // Destroying the last pod exposes the core
+                if pods == 0 {
+                    for (segment, ..) in alive.iter() {
+                        if let Ok((_, _, _, _, mut mask, mut material)) =
+                            q_segments.get_mut(*segment)
+                        {
+                            if mask.0 & AMMO == 0 {
+                                mask.0 = PLAYER | AMMO;
+                                *material = colors.hull.clone();
+                            }
+                        }
+                    }
+                }
+
+                if boss.timer.just_finished() {
+                    let launchers: Vec<&(Entity, Role, Vec2, u16)> = alive
+                        .iter()
+                        .filter(|(_, role, ..)| pods == 0 || *role == Role::Pod)
+                        .collect();
+                    if let Some((_, _, position, _)) = launchers.choose(&mut rng) {
+                        let saucer = rng.gen_bool(match boss.phase {
+                            BossPhase::Enraged => 0.5,
+                            _ => 0.3,
+                        });
+                        let aim = player
+                            .map(|player| wrapped_offset(*position, player, projection))
+                            .unwrap_or(Vec2::X)
+                            .normalize_or_zero();
+
+                        // Launches aim at the ship, deviating as much as
+                        // needed to leave it a chance. The launch is skipped
+                        // if no direction does.
+                        let launch = (0..safety.attempts.max(1))
+                            .map(|attempt| {
+                                let direction = if attempt == 0 {
+                                    aim
+                                } else {
+                                    let angle = rng.gen_range(-1.2..1.2_f32);
+                                    aim * angle.cos() + aim.perp() * angle.sin()
+                                };
+                                if saucer {
+                                    AsteroidSpawn {
+                                        asteroid: AsteroidClass::Saucer,
+                                        position: *position,
+                                        velocity: direction * 300.0,
+                                        spin: 5.0,
+                                        outline: None,
+                                        material: None,
+                                    }
+                                } else {
+                                    AsteroidSpawn {
+                                        asteroid: AsteroidClass::Small,
+                                        position: *position + direction * 40.0,
+                                        velocity: direction * rng.gen_range(100.0..160.0),
+                                        spin: rng.gen_range(-5.0..5.0),
+                                        outline: None,
+                                        material: None,
+                                    }
+                                }
+                            })
+                            .find(|launch| {
+                                safety.is_safe(
+                                    launch.asteroid,
+                                    (launch.position, launch.velocity),
+                                    ship,
+                                    projection,
+                                )
+                            });
+                        if let Some(launch) = launch {
+                            commands.spawn().insert(launch);
+                        }
+                    }
+                }
+            }
+            BossKind::GiantRock => {
+                let (_, _, center, _) = alive[0];
+                let damaged = boss.shed.saturating_sub(health) >= SHED_STEP;
+                let count = match boss.phase {
+                    BossPhase::Enraged => 3,
+                    _ => 2,
+                };
+
+                if damaged || boss.timer.just_finished() {
+                    if damaged {
+                        boss.shed = health;
+                    }
+                    let asteroid = if damaged {
+                        AsteroidClass::Small
+                    } else {
+                        AsteroidClass::Tiny
+                    };
+                    // Pieces heading for the ship are not shed
+                    for _ in 0..count {
+                        let piece = (0..safety.attempts.max(1))
+                            .map(|_| {
+                                let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+                                let direction = Vec2::new(angle.cos(), angle.sin());
+                                (
+                                    center + direction * 120.0,
+                                    direction * rng.gen_range(100.0..160.0),
+                                )
+                            })
+                            .find(|&trajectory| {
+                                safety.is_safe(asteroid, trajectory, ship, projection)
+                            });
+                        if let Some((position, velocity)) = piece {
+                            commands.spawn().insert(AsteroidSpawn {
+                                asteroid,
+                                position,
+                                velocity,
+                                spin: rng.gen_range(-5.0..5.0),
+                                outline: None,
+                                material: Some(AsteroidMaterial::Rock),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Health bar at the top of the screen, for all bosses together
+fn display_health_bar(
+    mut commands: Commands,
+    colors: Res<BossColors>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_bosses: Query<&Boss>,
+    q_health: Query<&Health, With<BossSegment>>,
+    mut q_bars: Query<(Entity, &HealthBar, &mut Transform, &mut Sprite)>,
+) {
+    let projection = match q_projection.single() {
+        Ok(projection) => projection,
+        Err(_) => return,
+    };
+
+    let (current, max) = q_bosses.iter().fold((0, 0), |(current, max), boss| {
+        let health: u16 = boss
+            .segments
+            .iter()
+            .filter_map(|segment| q_health.get(*segment).ok())
+            .map(|health| health.current)
+            .sum();
+        (current + health, max + boss.max_health)
+    });
+
+    if max == 0 {
+        for (e, ..) in q_bars.iter_mut() {
+            commands.entity(e).despawn();
+        }
+        return;
+    }
+
+    let position = Vec3::new(
+        (projection.left + projection.right) / 2.0,
+        projection.top - BAR_MARGIN,
+        20.0,
+    );
+    let width = BAR_WIDTH * current as f32 / max as f32;
+
+    if q_bars.iter_mut().next().is_none() {
+        for (bar, material, z) in [
+            (HealthBar::Back, colors.bar_back.clone(), 0.0),
+            (HealthBar::Fill, colors.bar_fill.clone(), 0.1),
+        ]
+        .iter()
+        .cloned()
+        {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material,
+                    transform: Transform::from_translation(position + Vec3::new(0.0, 0.0, z)),
+                    sprite: Sprite::new(Vec2::new(BAR_WIDTH, BAR_HEIGHT)),
+                    ..Default::default()
+                })
+                .insert(bar)
+                .insert(NoWrapProtection);
+        }
+    }
+
+    for (_, bar, mut transform, mut sprite) in q_bars.iter_mut() {
+        if let HealthBar::Fill = bar {
+            transform.translation.x = position.x - (BAR_WIDTH - width) / 2.0;
+            sprite.size = Vec2::new(width, BAR_HEIGHT);
+        }
+    }
+}
+
+fn prepare_resources(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(BossColors {
+        hull: materials.add(Color::rgb(1.0, 0.3, 0.6).into()),
+        shielded: materials.add(Color::rgb(0.4, 0.8, 1.0).into()),
+        rock: materials.add(Color::rgb(0.85, 0.85, 0.8).into()),
+        bar_back: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
+        bar_fill: materials.add(Color::rgb(0.9, 0.1, 0.2).into()),
+    });
+}
+
+fn exit(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        Or<(
+            With<Boss>,
+            With<BossSpawn>,
+            With<BossSegment>,
+            With<HealthBar>,
+        )>,
+    >,
+) {
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BossRules>()
+            .add_event::<BossDefeated>()
+            .add_startup_system(prepare_resources.system())
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(exit.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(spawn_bosses.system())
+                    .with_system(damage_bosses.system())
+                    .with_system(update_bosses.system())
+                    .with_system(display_health_bar.system()),
+            );
+    }
+}
@@ -0,0 +1,199 @@
+/// Camera effects
+/// Feedback of explosions on the `WrapCamera`: trauma-based screen shake, zoom
+/// punch and hit-stop. Gameplay systems only send `CameraEffect` events.
+///
+/// Trauma adds up from hits and decays over time, the shake growing with its
+/// square so that small hits barely move the view. The shake moves and tilts
+/// the camera transform and the zoom punch changes the projection scale: the
+/// projection bounds, which define the play area used for wrapping, are left
+/// untouched. Hit-stop dips the global `TimeScale` for a moment.
+use crate::{AppState, TimeScale, WrapCamera};
+use bevy::{
+    app::{AppBuilder, EventReader, Plugin},
+    core::{Time, Timer},
+    ecs::{
+        entity::Entity,
+        query::With,
+        schedule::SystemSet,
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    math::{Quat, Vec2},
+    render::camera::{Camera, CameraProjection, OrthographicProjection},
+    transform::components::Transform,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum CameraEffect {
+    /// Adds trauma, from 0 to 1
+    Shake(f32),
+    /// Zooms in by the given part of the view, then back
+    ZoomPunch(f32),
+    /// Slows time down to `scale` for `duration` seconds
+    HitStop { scale: f32, duration: f32 },
+}
+
+pub struct CameraRules {
+    /// Camera offset at full trauma, in pixels
+    pub max_offset: f32,
+    /// Camera tilt at full trauma, in radians
+    pub max_angle: f32,
+    /// Trauma lost each second
+    pub trauma_decay: f32,
+    /// Speed of the shake
+    pub frequency: f32,
+    /// Rate at which the zoom goes back to normal
+    pub zoom_recovery: f32,
+}
+
+impl Default for CameraRules {
+    fn default() -> Self {
+        CameraRules {
+            max_offset: 12.0,
+            max_angle: 0.04,
+            trauma_decay: 1.5,
+            frequency: 30.0,
+            zoom_recovery: 8.0,
+        }
+    }
+}
+
+/// Current state of the effects, on the camera
+#[derive(Default)]
+struct Effects {
+    trauma: f32,
+    zoom: f32,
+    hit_stop: Option<(Timer, f32)>,
+    elapsed: f32,
+    /// Shake offset applied to the camera on the last frame
+    offset: Vec2,
+}
+
+/// Smooth pseudo-random signal between -1 and 1
+fn noise(t: f32, seed: f32) -> f32 {
+    (t + seed).sin() * 0.6 + (t * 2.3 + seed * 1.7).sin() * 0.4
+}
+
+fn apply_effects(
+    time: Res<Time>,
+    rules: Res<CameraRules>,
+    mut time_scale: ResMut<TimeScale>,
+    mut events: EventReader<CameraEffect>,
+    mut query: Query<
+        (
+            &mut Effects,
+            &mut Transform,
+            &mut OrthographicProjection,
+            &mut Camera,
+        ),
+        With<WrapCamera>,
+    >,
+) {
+    let (mut effects, mut transform, mut projection, mut camera) = match query.single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+
+    for effect in events.iter() {
+        match *effect {
+            CameraEffect::Shake(trauma) => {
+                effects.trauma = (effects.trauma + trauma).clamp(0.0, 1.0);
+            }
+            CameraEffect::ZoomPunch(zoom) => effects.zoom = effects.zoom.max(zoom),
+            CameraEffect::HitStop { scale, duration } => {
+                // Overlapping hit-stops keep the slowest scale and the
+                // longest remaining time
+                let (remaining, slowest) = match effects.hit_stop.as_ref() {
+                    Some((timer, current)) => (
+                        timer.duration().as_secs_f32() - timer.elapsed_secs(),
+                        *current,
+                    ),
+                    None => (0.0, 1.0),
+                };
+                effects.hit_stop = Some((
+                    Timer::from_seconds(duration.max(remaining), false),
+                    scale.min(slowest),
+                ));
+            }
+        }
+    }
+
+    let delta = time.delta_seconds();
+    effects.elapsed += delta;
+
+    // Shake
+    let shake = effects.trauma * effects.trauma;
+    let t = effects.elapsed * rules.frequency;
+    let offset = Vec2::new(noise(t, 0.0), noise(t, 10.0)) * rules.max_offset * shake;
+    transform.translation += (offset - effects.offset).extend(0.0);
+    transform.rotation = Quat::from_rotation_z(noise(t, 20.0) * rules.max_angle * shake);
+    effects.offset = offset;
+    effects.trauma = (effects.trauma - rules.trauma_decay * delta).max(0.0);
+
+    // Zoom punch
+    let scale = 1.0 - effects.zoom;
+    if (projection.scale - scale).abs() > f32::EPSILON {
+        projection.scale = scale;
+        camera.projection_matrix = projection.get_projection_matrix();
+    }
+    effects.zoom *= (-rules.zoom_recovery * delta).exp();
+    if effects.zoom < 0.001 {
+        effects.zoom = 0.0;
+    }
+
+    // Hit-stop, timed in real time
+    if let Some((timer, scale)) = effects.hit_stop.as_mut() {
+        if timer.tick(time.delta()).finished() {
+            time_scale.global = 1.0;
+            effects.hit_stop = None;
+        } else {
+            time_scale.global = *scale;
+        }
+    }
+}
+
+fn enter(mut commands: Commands, query: Query<Entity, With<WrapCamera>>) {
+    for camera in query.iter() {
+        commands.entity(camera).insert(Effects::default());
+    }
+}
+
+/// Puts the camera and the time back to rest
+fn exit(
+    mut commands: Commands,
+    mut time_scale: ResMut<TimeScale>,
+    mut query: Query<
+        (
+            Entity,
+            &Effects,
+            &mut Transform,
+            &mut OrthographicProjection,
+            &mut Camera,
+        ),
+        With<WrapCamera>,
+    >,
+) {
+    for (entity, effects, mut transform, mut projection, mut camera) in query.iter_mut() {
+        transform.translation -= effects.offset.extend(0.0);
+        transform.rotation = Quat::IDENTITY;
+        projection.scale = 1.0;
+        camera.projection_matrix = projection.get_projection_matrix();
+        if effects.hit_stop.is_some() {
+            time_scale.global = 1.0;
+        }
+        commands.entity(entity).remove::<Effects>();
+    }
+}
+
+pub struct CameraEffectsPlugin;
+
+impl Plugin for CameraEffectsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<CameraEffect>()
+            .init_resource::<CameraRules>()
+            .add_system_set(SystemSet::on_enter(AppState::Game).with_system(enter.system()))
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(exit.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game).with_system(apply_effects.system()),
+            );
+    }
+}
@@ -32,10 +32,14 @@ pub struct CollisionLayer(pub u8);
 #[derive(Clone, Copy)]
 pub struct CollisionMask(pub u8);
 
+#[derive(Clone, Copy)]
 pub struct CollisionEvent {
     pub source: Entity,
     pub target: Entity,
     pub layer: u8,
+    /// Position and velocity of what hit the source, when the target entity
+    /// does not stand for it, like a particle of an emitter
+    pub contact: Option<(Vec2, Vec2)>,
 }
 
 #[derive(Default)]
@@ -44,13 +48,94 @@ pub struct Collider2D {
     pub position: Vec2,
 }
 
-fn check(
+/// Polygon points in world space
+fn world_polygon(points: &[Vec2], collider: &Collider2D, transform: &Transform) -> Vec<Vec2> {
+    points
+        .iter()
+        .map(|point| transform.mul_vec3(point.extend(0.0)).truncate() + collider.position)
+        .collect()
+}
+
+fn edges(polygon: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| (*a, *b))
+}
+
+/// Even-odd rule, works for concave polygons
+fn point_in_polygon(point: Vec2, polygon: &[Vec2]) -> bool {
+    edges(polygon)
+        .filter(|(a, b)| {
+            (a.y > point.y) != (b.y > point.y)
+                && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        })
+        .count()
+        % 2
+        == 1
+}
+
+fn segment_distance_squared(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((point - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance_squared(a + ab * t)
+}
+
+fn segments_intersect(a1: Vec2, a2: Vec2, b1: Vec2, b2: Vec2) -> bool {
+    let cross = |o: Vec2, a: Vec2, b: Vec2| (a - o).perp_dot(b - o);
+    let d1 = cross(b1, b2, a1);
+    let d2 = cross(b1, b2, a2);
+    let d3 = cross(a1, a2, b1);
+    let d4 = cross(a1, a2, b2);
+    (d1 > 0.0) != (d2 > 0.0) && (d3 > 0.0) != (d4 > 0.0)
+}
+
+fn polygons_overlap(a: &[Vec2], b: &[Vec2]) -> bool {
+    edges(a).any(|(a1, a2)| edges(b).any(|(b1, b2)| segments_intersect(a1, a2, b1, b2)))
+        || matches!(a.first(), Some(point) if point_in_polygon(*point, b))
+        || matches!(b.first(), Some(point) if point_in_polygon(*point, a))
+}
+
+fn polygon_circle_overlap(polygon: &[Vec2], center: Vec2, radius: f32) -> bool {
+    point_in_polygon(center, polygon)
+        || edges(polygon).any(|(a, b)| segment_distance_squared(center, a, b) < radius * radius)
+}
+
+/// Whether two colliders overlap
+pub fn check(
     collider_a: &Collider2D,
-    position_a: Vec2,
+    transform_a: &Transform,
     collider_b: &Collider2D,
-    position_b: Vec2,
+    transform_b: &Transform,
 ) -> bool {
+    let position_a = transform_a.translation.truncate();
+    let position_b = transform_b.translation.truncate();
+
     match (&collider_a.shape, &collider_b.shape) {
+        (Shape2D::Polygon(points), shape_b) => {
+            let polygon = world_polygon(points, collider_a, transform_a);
+            let center_b = position_b + collider_b.position;
+            match shape_b {
+                Shape2D::Polygon(points_b) => {
+                    polygons_overlap(&polygon, &world_polygon(points_b, collider_b, transform_b))
+                }
+                Shape2D::Circle(radius) => polygon_circle_overlap(&polygon, center_b, *radius),
+                Shape2D::Rectangle(extends) => polygons_overlap(
+                    &polygon,
+                    &[
+                        center_b - *extends,
+                        center_b + Vec2::new(extends.x, -extends.y),
+                        center_b + *extends,
+                        center_b + Vec2::new(-extends.x, extends.y),
+                    ],
+                ),
+            }
+        }
+        (_, Shape2D::Polygon(_)) => check(collider_b, transform_b, collider_a, transform_a),
         (Shape2D::Rectangle(extends_a), Shape2D::Rectangle(extends_b)) => {
             let a_min = (collider_a.position + position_a) - *extends_a;
             let a_max = (collider_a.position + position_a) + *extends_a;
@@ -73,7 +158,7 @@ fn check(
             ) < radius_b.powf(2.0)
         }
         (Shape2D::Circle(_), Shape2D::Rectangle(_)) => {
-            check(collider_b, position_b, collider_a, position_a)
+            check(collider_b, transform_b, collider_a, transform_a)
         }
     }
 }
@@ -99,9 +184,9 @@ fn transform_based_check(
             if layer > 0u8 {
                 if check(
                     source_collider,
-                    source_transform.translation.into(),
+                    source_transform,
                     target_collider,
-                    target_transform.translation.into(),
+                    target_transform,
                 ) {
                     let source = if let Some(ghost) = source_ghost {
                         ghost.target
@@ -113,6 +198,7 @@ fn transform_based_check(
                         source,
                         target,
                         layer,
+                        contact: None,
                     });
                 }
             }
@@ -1,47 +1,455 @@
 use crate::{
-    AppState, AudioChannels, Collider2D, CollisionEvent, CollisionLayer, CollisionMask, Shape2D,
-    SoundEffects, Velocity, Wrap, AMMO, OBSTACLE,
+    missile_thrust, recycle_or_despawn, Acceleration, AppState, AudioChannels, Collider2D,
+    CollisionEvent, CollisionLayer, CollisionMask, Friction, Ghost, Homing, Missile, Pool,
+    PoolPlugin, Poolable, Recycle, Shape2D, SoundEffects, Thrust, Velocity, Wrap, Wrapped, AMMO,
+    OBSTACLE,
 };
 use bevy::{
-    app::{AppBuilder, EventReader, Plugin},
+    app::{AppBuilder, CoreStage, EventReader, EventWriter, Plugin},
     asset::{Assets, Handle},
     core::{Time, Timer},
+    diagnostic::DiagnosticId,
     ecs::{
         entity::Entity,
         query::{With, Without},
         schedule::SystemSet,
+        system::RemovedComponents,
         system::{Commands, IntoSystem, Query, Res, ResMut},
+        world::EntityMut,
     },
-    math::Vec2,
+    math::{Quat, Vec2},
     render::color::Color,
     sprite::Sprite,
     sprite::{entity::SpriteBundle, ColorMaterial},
     transform::components::Transform,
+    utils::{HashMap, HashSet},
 };
 use bevy_kira_audio::Audio;
 use rand::prelude::*;
 use std::time::Duration;
 
 pub struct Firing;
-pub struct FireCooldown(Timer);
+
+/// Time before the next shot, and shots left in the current burst. The timer
+/// repeats, so that the time elapsed past a shot counts towards the next one.
+pub struct FireCooldown {
+    timer: Timer,
+    burst: u8,
+}
+
+impl FireCooldown {
+    /// Ticks the cooldown of a weapon which stopped firing, whether it is
+    /// over. An interrupted burst still waits for the full weapon cooldown.
+    fn tick_idle(&mut self, weapon: &Weapon, delta: Duration) -> bool {
+        if self.burst > 0 {
+            self.burst = 0;
+            self.timer.set_duration(weapon.cooldown);
+        }
+        self.timer.tick(delta).just_finished()
+    }
+}
 
 const FLOOR_SPEED: f32 = 200.0;
 const INITIAL_SPEED: f32 = 500.0;
 const PEW_PEW_SPEED: u64 = 300;
 const PEW_PEW_SIZE: f32 = 3.0;
 const FIRE_ANGLE_ERROR: f32 = 0.03;
+const FADE_OUT_DURATION: f32 = 0.2;
 
 pub struct Fire;
-pub struct FireColors(Vec<Handle<ColorMaterial>>);
 
+impl Poolable for Fire {
+    const NAME: &'static str = "fire";
+    const SIZE: DiagnosticId = DiagnosticId::from_u128(0x6d1f4a2e_93b8_4c57_a0e2_5b7c1d38f904);
+    const MISSES: DiagnosticId = DiagnosticId::from_u128(0x2c8e7b41_0f6d_4e93_b1a5_c94f27e6d013);
+
+    fn reset(entity: &mut EntityMut) {
+        entity.remove::<Fire>();
+        entity.remove::<Shooter>();
+        entity.remove::<Velocity>();
+        entity.remove::<Collider2D>();
+        entity.remove::<CollisionLayer>();
+        entity.remove::<CollisionMask>();
+        entity.remove::<Wrap>();
+        entity.remove::<Wrapped>();
+        entity.remove::<Expiry>();
+        entity.remove::<Piercing>();
+        entity.remove::<Hit>();
+        entity.remove::<Missile>();
+        entity.remove::<Acceleration>();
+        entity.remove::<Thrust>();
+        entity.remove::<Friction>();
+    }
+}
+
+/// Overrides the shooter orientation as the aiming direction (radians)
+pub struct Aim(pub f32);
+
+/// Entity which fired a projectile
+pub struct Shooter(pub Entity);
+
+/// Projectiles going through up to `hits` obstacles, stopping on the next
+/// one. An obstacle touched over several frames counts once.
+pub struct Piercing {
+    hits: u8,
+    last: Option<Entity>,
+}
+
+impl Piercing {
+    pub fn new(hits: u8) -> Self {
+        Piercing { hits, last: None }
+    }
+
+    /// Whether the projectile goes through the obstacle it touches
+    fn pass(&mut self, obstacle: Entity) -> bool {
+        if self.last == Some(obstacle) {
+            return true;
+        }
+        self.last = Some(obstacle);
+        if self.hits > 0 {
+            self.hits -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Projectiles which hit something
+struct Hit;
+
+/// Emitted when a projectile stops being a threat without having hit anything
+pub struct ShotMissed {
+    pub shooter: Entity,
+}
+
+/// Emitted the first time a projectile hits something
+pub struct ShotHit {
+    pub shooter: Entity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Palette {
+    Fire,
+    Plasma,
+    Laser,
+}
+
+pub struct FireColors(HashMap<Palette, Vec<Handle<ColorMaterial>>>);
+
+/// What a weapon shoots
+#[derive(Debug, Clone, Copy)]
+pub enum Projectile {
+    Bullet,
+    Missile(Homing),
+}
+
+/// How long a projectile lives
+#[derive(Debug, Clone, Copy)]
+pub enum Lifetime {
+    /// Number of times the projectile may wrap before vanishing
+    Wraps(u8),
+    /// Distance traveled, in pixels
+    Distance(f32),
+    Seconds(f32),
+}
+
+/// Tracks the range of a projectile with a `Distance` or `Seconds` lifetime,
+/// and fades it out once expired
+pub struct Expiry {
+    lifetime: Lifetime,
+    traveled: f32,
+    elapsed: f32,
+    fading: Option<(Timer, Handle<ColorMaterial>)>,
+}
+
+impl Expiry {
+    fn expired(&self) -> bool {
+        match self.lifetime {
+            Lifetime::Wraps(_) => false,
+            Lifetime::Distance(distance) => self.traveled >= distance,
+            Lifetime::Seconds(seconds) => self.elapsed >= seconds,
+        }
+    }
+}
+
+/// How projectiles of a single shot are oriented
+#[derive(Debug, Clone, Copy)]
+pub enum Spread {
+    /// Each projectile deviates randomly, up to the given ratio of PI
+    Random(f32),
+    /// Projectiles are evenly spread over the given angle (radians)
+    Fan(f32),
+}
+
+/// Describes what a shooter fires.
+/// Each shot emits `projectiles` projectiles. Shots are grouped in bursts of
+/// `burst` shots `burst_delay` apart, bursts being `cooldown` apart.
+#[derive(Debug, Clone, Copy)]
+pub struct Weapon {
+    pub projectile: Projectile,
+    pub projectiles: u8,
+    pub spread: Spread,
+    pub speed: f32,
+    pub lifetime: Lifetime,
+    /// Maximum number of live projectiles per shooter
+    pub max_live: Option<u8>,
+    pub size: f32,
+    pub palette: Palette,
+    pub cooldown: Duration,
+    pub burst: u8,
+    pub burst_delay: Duration,
+    /// Obstacles each projectile goes through before stopping
+    pub piercing: u8,
+    /// Collision layer of the projectiles
+    pub layer: u8,
+    /// Collision mask of the projectiles
+    pub mask: u8,
+}
+
+impl Default for Weapon {
+    fn default() -> Self {
+        Weapon {
+            projectile: Projectile::Bullet,
+            projectiles: 1,
+            spread: Spread::Random(FIRE_ANGLE_ERROR),
+            speed: INITIAL_SPEED,
+            lifetime: Lifetime::Wraps(1),
+            max_live: None,
+            size: PEW_PEW_SIZE,
+            palette: Palette::Fire,
+            cooldown: Duration::from_millis(PEW_PEW_SPEED),
+            burst: 1,
+            burst_delay: Duration::default(),
+            piercing: 0,
+            layer: AMMO,
+            mask: OBSTACLE,
+        }
+    }
+}
+
+impl Weapon {
+    pub fn cannon() -> Self {
+        Weapon::default()
+    }
+
+    /// Arcade cabinet rules: four bullets at most, with a fixed range
+    pub fn classic() -> Self {
+        Weapon {
+            lifetime: Lifetime::Distance(700.0),
+            max_live: Some(4),
+            cooldown: Duration::from_millis(150),
+            ..Default::default()
+        }
+    }
+
+    pub fn spread_shot() -> Self {
+        Weapon {
+            projectiles: 5,
+            spread: Spread::Fan(0.6),
+            speed: 450.0,
+            palette: Palette::Plasma,
+            cooldown: Duration::from_millis(500),
+            ..Default::default()
+        }
+    }
+
+    pub fn rapid_fire() -> Self {
+        Weapon {
+            spread: Spread::Random(0.06),
+            speed: 550.0,
+            size: 2.0,
+            cooldown: Duration::from_millis(100),
+            ..Default::default()
+        }
+    }
+
+    pub fn piercing_rail() -> Self {
+        Weapon {
+            spread: Spread::Random(0.0),
+            speed: 1200.0,
+            size: 4.0,
+            palette: Palette::Laser,
+            cooldown: Duration::from_millis(800),
+            piercing: 2,
+            ..Default::default()
+        }
+    }
+
+    pub fn burst_laser() -> Self {
+        Weapon {
+            spread: Spread::Random(0.01),
+            speed: 800.0,
+            palette: Palette::Laser,
+            cooldown: Duration::from_millis(600),
+            burst: 4,
+            burst_delay: Duration::from_millis(60),
+            ..Default::default()
+        }
+    }
+
+    pub fn homing_missiles() -> Self {
+        Weapon {
+            projectile: Projectile::Missile(Homing {
+                thrust: 700.0,
+                turn_rate: 40.0,
+                blast_radius: 60.0,
+            }),
+            projectiles: 2,
+            spread: Spread::Fan(1.2),
+            speed: 200.0,
+            lifetime: Lifetime::Wraps(2),
+            size: 4.0,
+            cooldown: Duration::from_millis(900),
+            ..Default::default()
+        }
+    }
+
+    /// Stronger version of the weapon: two more projectiles, fanned out
+    pub fn upgraded(self) -> Self {
+        Weapon {
+            projectiles: self.projectiles.saturating_add(2),
+            spread: match self.spread {
+                Spread::Random(_) => Spread::Fan(0.3),
+                Spread::Fan(angle) => Spread::Fan(angle + 0.3),
+            },
+            max_live: self.max_live.map(|max| max.saturating_mul(3)),
+            ..self
+        }
+    }
+
+    /// Changes the collision layer and mask of the projectiles
+    pub fn with_layers(self, layer: u8, mask: u8) -> Self {
+        Weapon {
+            layer,
+            mask,
+            ..self
+        }
+    }
+
+    /// Angles of the projectiles of a shot, relative to the aiming direction
+    fn angles(&self, rng: &mut impl Rng) -> Vec<f32> {
+        match self.spread {
+            Spread::Random(error) => (0..self.projectiles)
+                .map(|_| {
+                    let error = std::f32::consts::PI * error;
+                    if error > 0.0 {
+                        rng.gen_range(-error..error)
+                    } else {
+                        0.0
+                    }
+                })
+                .collect(),
+            Spread::Fan(angle) => {
+                if self.projectiles <= 1 {
+                    vec![0.0]
+                } else {
+                    let step = angle / (self.projectiles - 1) as f32;
+                    (0..self.projectiles)
+                        .map(|i| i as f32 * step - angle / 2.0)
+                        .collect()
+                }
+            }
+        }
+    }
+}
+
+/// Marks the projectiles which hit something, releasing those which do not
+/// go through what they hit
 fn destroy_on_collision(
     mut commands: Commands,
     mut events: EventReader<CollisionEvent>,
-    query: Query<Entity, With<Fire>>,
+    mut hits: EventWriter<ShotHit>,
+    pool: Res<Pool<Fire>>,
+    mut query: Query<(Entity, &Shooter, Option<&mut Piercing>, Option<&Hit>), With<Fire>>,
+    q_ghosts: Query<&Ghost>,
 ) {
+    // An obstacle and its ghosts are the same obstacle
+    let resolve = |entity: Entity| {
+        q_ghosts
+            .get(entity)
+            .map(|ghost| ghost.target)
+            .unwrap_or(entity)
+    };
+
+    let mut hit = HashSet::default();
+    let mut released = HashSet::default();
     for collision in events.iter() {
-        if let Ok(id) = query.get(collision.source) {
-            commands.entity(id).despawn();
+        if let Ok((id, shooter, piercing, already_hit)) = query.get_mut(collision.source) {
+            if released.contains(&id) {
+                continue;
+            }
+            if already_hit.is_none() && hit.insert(id) {
+                commands.entity(id).insert(Hit);
+                hits.send(ShotHit { shooter: shooter.0 });
+            }
+            let through = match piercing {
+                Some(mut piercing) => piercing.pass(resolve(collision.target)),
+                None => false,
+            };
+            if !through {
+                released.insert(id);
+                pool.release(&mut commands, id);
+            }
+        }
+    }
+}
+
+/// Fades out projectiles which went past their range. A fading projectile
+/// no longer collides, and gets its own material so its alpha can change.
+fn expire_projectiles(
+    mut commands: Commands,
+    mut missed: EventWriter<ShotMissed>,
+    time: Res<Time>,
+    pool: Res<Pool<Fire>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(
+        Entity,
+        &mut Expiry,
+        &Velocity,
+        &Handle<ColorMaterial>,
+        &Shooter,
+        Option<&Wrapped>,
+        Option<&Hit>,
+    )>,
+) {
+    for (e, mut expiry, velocity, material, shooter, wrapped, hit) in query.iter_mut() {
+        if let Some((timer, material)) = expiry.fading.as_mut() {
+            timer.tick(time.delta());
+            if timer.finished() {
+                pool.release(&mut commands, e);
+            } else if let Some(material) = materials.get_mut(&*material) {
+                material.color.set_a(1.0 - timer.percent());
+            }
+            continue;
+        }
+
+        expiry.traveled += velocity.translation.length() * time.delta_seconds();
+        expiry.elapsed += time.delta_seconds();
+
+        if expiry.expired() {
+            if hit.is_none() {
+                missed.send(ShotMissed { shooter: shooter.0 });
+            }
+
+            let color = materials
+                .get(material)
+                .map(|material| material.color)
+                .unwrap_or(Color::WHITE);
+            let fading = materials.add(color.into());
+
+            commands
+                .entity(e)
+                .insert(fading.clone())
+                .remove::<CollisionLayer>()
+                .remove::<CollisionMask>();
+            if let Some(wrapped) = wrapped {
+                for ghost in wrapped.ghosts.iter().flatten() {
+                    commands.entity(*ghost).insert(fading.clone());
+                }
+            }
+
+            expiry.fading = Some((Timer::from_seconds(FADE_OUT_DURATION, false), fading));
         }
     }
 }
@@ -49,10 +457,11 @@ fn destroy_on_collision(
 fn remove_cooldown(
     mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(Entity, &mut FireCooldown), Without<Firing>>,
+    mut query: Query<(Entity, Option<&Weapon>, &mut FireCooldown), Without<Firing>>,
 ) {
-    for (e, mut cooldown) in query.iter_mut() {
-        if cooldown.0.tick(time.delta()).just_finished() {
+    for (e, weapon, mut cooldown) in query.iter_mut() {
+        let weapon = weapon.copied().unwrap_or_default();
+        if cooldown.tick_idle(&weapon, time.delta()) {
             commands.entity(e).remove::<FireCooldown>();
         }
     }
@@ -65,88 +474,205 @@ pub fn spawn_fires(
     audio: Res<Audio>,
     fx: Res<SoundEffects>,
     audio_channels: Res<AudioChannels>,
+    mut pool: ResMut<Pool<Fire>>,
     mut query: Query<
         (
             Entity,
             &Transform,
             Option<&Velocity>,
+            Option<&Weapon>,
+            Option<&Aim>,
             Option<&mut FireCooldown>,
         ),
         With<Firing>,
     >,
+    q_live: Query<(&Shooter, Option<&Expiry>), With<Fire>>,
 ) {
     let mut rng = thread_rng();
 
-    for (e, transform, spawner_velocity, fire_cooldown) in query.iter_mut() {
+    // Fading projectiles do not count towards the limit
+    let mut live = HashMap::default();
+    for (shooter, expiry) in q_live.iter() {
+        if expiry.and_then(|expiry| expiry.fading.as_ref()).is_none() {
+            *live.entry(shooter.0).or_insert(0_usize) += 1;
+        }
+    }
+
+    for (e, transform, spawner_velocity, weapon, aim, fire_cooldown) in query.iter_mut() {
+        let weapon = weapon.copied().unwrap_or_default();
         let fire = {
             if let Some(mut fire_cooldown) = fire_cooldown {
-                fire_cooldown.0.tick(time.delta()).just_finished()
+                if fire_cooldown.timer.tick(time.delta()).just_finished() {
+                    if fire_cooldown.burst > 0 {
+                        fire_cooldown.burst -= 1;
+                    } else {
+                        fire_cooldown.burst = weapon.burst.saturating_sub(1);
+                    }
+                    let duration = if fire_cooldown.burst > 0 {
+                        weapon.burst_delay
+                    } else {
+                        weapon.cooldown
+                    };
+                    fire_cooldown.timer.set_duration(duration);
+                    true
+                } else {
+                    false
+                }
             } else {
-                commands.entity(e).insert(FireCooldown(Timer::new(
-                    Duration::from_millis(PEW_PEW_SPEED),
-                    true,
-                )));
+                let burst = weapon.burst.saturating_sub(1);
+                commands.entity(e).insert(FireCooldown {
+                    timer: Timer::new(
+                        if burst > 0 {
+                            weapon.burst_delay
+                        } else {
+                            weapon.cooldown
+                        },
+                        true,
+                    ),
+                    burst,
+                });
                 true
             }
         };
 
-        if fire {
-            audio.play_in_channel(fx.fire.clone(), &audio_channels.fx);
+        let available = weapon.max_live.map_or(usize::MAX, |max| {
+            (max as usize).saturating_sub(live.get(&e).copied().unwrap_or(0))
+        });
 
-            // Calculate initial velocity by computing vector*INITIAL_SPEED
-            let rotation = transform.rotation.to_axis_angle();
-            let mut angle = std::f32::consts::PI / 2.0 + rotation.0.z * rotation.1;
+        if fire && available > 0 {
+            audio.play_in_channel(fx.fire.clone(), &audio_channels.fx);
 
-            let error = std::f32::consts::PI * FIRE_ANGLE_ERROR;
-            angle += rng.gen_range(-error..error);
+            let aim = aim.map_or_else(
+                || {
+                    let rotation = transform.rotation.to_axis_angle();
+                    std::f32::consts::PI / 2.0 + rotation.0.z * rotation.1
+                },
+                |aim| aim.0,
+            );
+            let palette = &colors.0[&weapon.palette];
 
-            let mut velocity = Vec2::new(angle.cos() * INITIAL_SPEED, angle.sin() * INITIAL_SPEED);
-            if let Some(&spawner_velocity) = spawner_velocity {
-                velocity += spawner_velocity.translation;
-            }
+            for angle in weapon.angles(&mut rng).into_iter().take(available) {
+                // Calculate initial velocity by computing vector*speed
+                let angle = aim + angle;
+                let mut velocity =
+                    Vec2::new(angle.cos() * weapon.speed, angle.sin() * weapon.speed);
+                if let Some(&spawner_velocity) = spawner_velocity {
+                    velocity += spawner_velocity.translation;
+                }
 
-            // If the final velocity norm is under a given floor, we re-set it
-            if velocity.length() < FLOOR_SPEED {
-                velocity = velocity.normalize() * FLOOR_SPEED;
-            }
+                // If the final velocity norm is under a given floor, we re-set it
+                if velocity.length() < FLOOR_SPEED {
+                    velocity = velocity.normalize() * FLOOR_SPEED;
+                }
 
-            let position = transform.translation;
+                let position = transform.translation;
 
-            let size = Vec2::new(PEW_PEW_SIZE, PEW_PEW_SIZE);
-            commands
-                .spawn_bundle(SpriteBundle {
-                    material: colors.0[rng.gen_range(0..colors.0.len())].clone(),
-                    transform: Transform::from_translation(position),
+                let size = Vec2::new(weapon.size, weapon.size);
+                let projectile = pool.acquire(&mut commands);
+                let mut projectile = commands.entity(projectile);
+                projectile.insert_bundle(SpriteBundle {
+                    material: palette[rng.gen_range(0..palette.len())].clone(),
+                    transform: Transform {
+                        translation: position,
+                        rotation: Quat::from_rotation_z(angle - std::f32::consts::PI / 2.0),
+                        ..Default::default()
+                    },
                     sprite: Sprite::new(size),
                     ..Default::default()
-                })
-                .insert(Velocity::new(velocity, 0.0))
-                .insert(Wrap::from_count(1))
-                .insert(Collider2D {
-                    shape: Shape2D::Rectangle(size),
-                    ..Default::default()
-                })
-                .insert(Fire)
-                .insert(CollisionLayer(AMMO))
-                .insert(CollisionMask(OBSTACLE));
+                });
+                projectile
+                    .insert(Velocity::new(velocity, 0.0))
+                    .insert(Collider2D {
+                        shape: Shape2D::Rectangle(size),
+                        ..Default::default()
+                    })
+                    .insert(Fire)
+                    .insert(Shooter(e))
+                    .insert(CollisionLayer(weapon.layer))
+                    .insert(CollisionMask(weapon.mask));
+
+                match weapon.lifetime {
+                    Lifetime::Wraps(count) => {
+                        projectile.insert(Wrap::from_count(count));
+                    }
+                    lifetime => {
+                        projectile.insert(Wrap::default()).insert(Expiry {
+                            lifetime,
+                            traveled: 0.0,
+                            elapsed: 0.0,
+                            fading: None,
+                        });
+                    }
+                }
+
+                if weapon.piercing > 0 {
+                    projectile.insert(Piercing::new(weapon.piercing));
+                }
+
+                if let Projectile::Missile(homing) = weapon.projectile {
+                    projectile
+                        .insert(Missile::new(homing))
+                        .insert(Acceleration::default())
+                        .insert(missile_thrust(&homing))
+                        .insert(Friction(1.0));
+                }
+            }
         }
     }
 }
 
 fn prepare_resources(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    let mut palettes = HashMap::default();
+
     // Added the palette of fire colors
-    commands.insert_resource(FireColors(vec![
-        materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-        materials.add(Color::rgb(1.0, 0.35, 0.0).into()),
-        materials.add(Color::rgb(1.0, 0.60, 0.0).into()),
-        materials.add(Color::rgb(1.0, 0.81, 0.0).into()),
-        materials.add(Color::rgb(1.0, 0.91, 0.03).into()),
-    ]));
+    palettes.insert(
+        Palette::Fire,
+        vec![
+            materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+            materials.add(Color::rgb(1.0, 0.35, 0.0).into()),
+            materials.add(Color::rgb(1.0, 0.60, 0.0).into()),
+            materials.add(Color::rgb(1.0, 0.81, 0.0).into()),
+            materials.add(Color::rgb(1.0, 0.91, 0.03).into()),
+        ],
+    );
+    palettes.insert(
+        Palette::Plasma,
+        vec![
+            materials.add(Color::rgb(0.55, 0.2, 1.0).into()),
+            materials.add(Color::rgb(0.8, 0.35, 1.0).into()),
+            materials.add(Color::rgb(1.0, 0.5, 0.9).into()),
+        ],
+    );
+    palettes.insert(
+        Palette::Laser,
+        vec![
+            materials.add(Color::rgb(0.3, 1.0, 1.0).into()),
+            materials.add(Color::rgb(0.6, 1.0, 0.9).into()),
+            materials.add(Color::rgb(0.9, 1.0, 1.0).into()),
+        ],
+    );
+
+    commands.insert_resource(FireColors(palettes));
 }
 
-fn despawn_all_fires(mut commands: Commands, query: Query<Entity, With<Fire>>) {
-    for e in query.iter() {
-        commands.entity(e).despawn();
+/// Projectiles lasting a number of wraps miss once they stop wrapping. `Wrap`
+/// is removed through commands at the end of the update stage, and removals
+/// are forgotten at the end of the frame: this runs in between.
+fn detect_missed_wraps(
+    mut missed: EventWriter<ShotMissed>,
+    unwrapped: RemovedComponents<Wrap>,
+    query: Query<&Shooter, (With<Fire>, Without<Expiry>, Without<Hit>)>,
+) {
+    for entity in unwrapped.iter() {
+        if let Ok(shooter) = query.get(entity) {
+            missed.send(ShotMissed { shooter: shooter.0 });
+        }
+    }
+}
+
+fn despawn_all_fires(mut commands: Commands, query: Query<(Entity, Option<&Recycle>), With<Fire>>) {
+    for (e, recycle) in query.iter() {
+        recycle_or_despawn(&mut commands, e, recycle);
     }
 }
 
@@ -154,7 +680,10 @@ pub struct FirePlugin;
 
 impl Plugin for FirePlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(prepare_resources.system())
+        app.add_plugin(PoolPlugin::<Fire>::default())
+            .add_event::<ShotMissed>()
+            .add_event::<ShotHit>()
+            .add_startup_system(prepare_resources.system())
             .add_system_set(
                 SystemSet::on_enter(AppState::Game).with_system(despawn_all_fires.system()),
             )
@@ -162,7 +691,117 @@ impl Plugin for FirePlugin {
                 SystemSet::on_update(AppState::Game)
                     .with_system(spawn_fires.system())
                     .with_system(remove_cooldown.system())
+                    .with_system(expire_projectiles.system())
                     .with_system(destroy_on_collision.system()),
-            );
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, detect_missed_wraps.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{
+        app::Events,
+        ecs::{
+            schedule::{Stage, SystemStage},
+            world::World,
+        },
+    };
+
+    fn unwrap_all(mut commands: Commands, query: Query<Entity, With<Wrap>>) {
+        for e in query.iter() {
+            commands.entity(e).remove::<Wrap>();
+        }
+    }
+
+    /// Shooter of a shot spawned by `spawn`, and the shooters of the missed
+    /// shots once `Wrap` is removed in the update stage
+    fn missed_after_unwrap(spawn: impl Fn(&mut World, Entity)) -> (Entity, Vec<Entity>) {
+        let mut world = World::default();
+        world.insert_resource(Events::<ShotMissed>::default());
+        let shooter = world.spawn().id();
+        spawn(&mut world, shooter);
+
+        let mut update = SystemStage::parallel();
+        update.add_system(unwrap_all.system());
+        let mut post_update = SystemStage::parallel();
+        post_update.add_system(detect_missed_wraps.system());
+        update.run(&mut world);
+        post_update.run(&mut world);
+
+        let events = world.get_resource::<Events<ShotMissed>>().unwrap();
+        let missed = events
+            .get_reader()
+            .iter(events)
+            .map(|missed| missed.shooter)
+            .collect();
+        (shooter, missed)
+    }
+
+    #[test]
+    fn unwrapped_shot_misses() {
+        let (shooter, missed) = missed_after_unwrap(|world, e| {
+            world
+                .spawn()
+                .insert(Fire)
+                .insert(Shooter(e))
+                .insert(Wrap::from_count(0));
+        });
+        assert_eq!(missed, vec![shooter]);
+    }
+
+    #[test]
+    fn unwrapped_hit_or_expiring_shot_does_not_miss() {
+        let (_, missed) = missed_after_unwrap(|world, e| {
+            world
+                .spawn()
+                .insert(Fire)
+                .insert(Shooter(e))
+                .insert(Wrap::from_count(0))
+                .insert(Hit);
+        });
+        assert!(missed.is_empty());
+
+        let (_, missed) = missed_after_unwrap(|world, e| {
+            world
+                .spawn()
+                .insert(Fire)
+                .insert(Shooter(e))
+                .insert(Wrap::default())
+                .insert(Expiry {
+                    lifetime: Lifetime::Seconds(1.0),
+                    traveled: 0.0,
+                    elapsed: 0.0,
+                    fading: None,
+                });
+        });
+        assert!(missed.is_empty());
+    }
+
+    #[test]
+    fn interrupted_bursts_wait_for_the_full_cooldown() {
+        let weapon = Weapon::burst_laser();
+        let mut cooldown = FireCooldown {
+            timer: Timer::new(weapon.burst_delay, true),
+            burst: 2,
+        };
+
+        assert!(!cooldown.tick_idle(&weapon, weapon.burst_delay));
+        assert!(!cooldown.tick_idle(&weapon, weapon.cooldown / 2));
+        assert!(cooldown.tick_idle(&weapon, weapon.cooldown / 2));
+    }
+
+    #[test]
+    fn piercing_rounds_stop_once_out_of_hits() {
+        let mut world = World::default();
+        let (first, second, third) = (world.spawn().id(), world.spawn().id(), world.spawn().id());
+
+        let mut piercing = Piercing::new(2);
+        assert!(piercing.pass(first));
+        // Still overlapping the same obstacle
+        assert!(piercing.pass(first));
+        assert!(piercing.pass(second));
+        assert!(!piercing.pass(third));
     }
 }
@@ -0,0 +1,264 @@
+/// Asteroid fragmentation
+/// What an asteroid breaks into is data: a `FragmentationTable` gives, for
+/// each class, the class of its pieces, how many there are, how fast they go,
+/// the cone they are thrown in and how much of the parent velocity they keep.
+///
+/// `fragment` turns an `Impact` into the `Spawn`s of the pieces. It only
+/// depends on its arguments and the given random generator.
+use crate::{AsteroidClass, AsteroidMaterial, AsteroidSpawn, Outline};
+use bevy::math::{Quat, Vec2};
+use rand::prelude::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fragmentation {
+    /// Class of the pieces
+    pub child: AsteroidClass,
+    /// Inclusive range of the number of pieces
+    pub count: (usize, usize),
+    /// Speed range of the pieces, relative to the parent
+    pub speed: (f32, f32),
+    /// Angle of the cone, centered on the impact direction, in which pieces
+    /// are thrown
+    pub spread: f32,
+    /// Part of the parent velocity kept by the pieces
+    pub inherit_velocity: f32,
+}
+
+pub struct FragmentationTable(pub Vec<(AsteroidClass, Fragmentation)>);
+
+impl Default for FragmentationTable {
+    fn default() -> Self {
+        FragmentationTable(vec![
+            (
+                AsteroidClass::Big,
+                Fragmentation {
+                    child: AsteroidClass::Small,
+                    count: (2, 4),
+                    speed: (50.0, 150.0),
+                    spread: std::f32::consts::FRAC_PI_2,
+                    inherit_velocity: 0.5,
+                },
+            ),
+            (
+                AsteroidClass::Small,
+                Fragmentation {
+                    child: AsteroidClass::Tiny,
+                    count: (2, 4),
+                    speed: (50.0, 150.0),
+                    spread: std::f32::consts::FRAC_PI_3 * 2.0,
+                    inherit_velocity: 0.5,
+                },
+            ),
+        ])
+    }
+}
+
+impl FragmentationTable {
+    pub fn get(&self, asteroid: AsteroidClass) -> Option<&Fragmentation> {
+        self.0
+            .iter()
+            .find(|(class, _)| *class == asteroid)
+            .map(|(_, fragmentation)| fragmentation)
+    }
+}
+
+/// A destroyed asteroid and what hit it
+#[derive(Debug, Clone, Copy)]
+pub struct Impact<'a> {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub rotation: Quat,
+    pub outline: Option<&'a Outline>,
+    pub material: Option<AsteroidMaterial>,
+    /// Position and velocity of whatever hit the asteroid
+    pub hit_position: Vec2,
+    pub hit_velocity: Vec2,
+}
+
+impl Impact<'_> {
+    /// Direction in which pieces are pushed: away from the hit point, and
+    /// along the velocity of what hit
+    fn direction(&self, rng: &mut impl Rng) -> Vec2 {
+        let direction = (self.position - self.hit_position).normalize_or_zero()
+            + self.hit_velocity.normalize_or_zero();
+        if direction.length_squared() > 1e-6 {
+            direction.normalize()
+        } else {
+            let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+            Vec2::new(angle.cos(), angle.sin())
+        }
+    }
+}
+
+/// Pieces of an asteroid destroyed by `impact`. Outlined asteroids are cut
+/// into fitting pieces, which keep their place and orientation.
+pub fn fragment(
+    fragmentation: &Fragmentation,
+    impact: &Impact,
+    rng: &mut impl Rng,
+) -> Vec<AsteroidSpawn> {
+    let (extra, speed_factor) = impact
+        .material
+        .map_or((0, 1.0), AsteroidMaterial::shattering);
+    let (min, max) = fragmentation.count;
+    let count = rng.gen_range(min..=max.max(min)) + extra;
+
+    let pieces: Vec<(Vec2, Option<Outline>)> = match impact.outline {
+        Some(outline) => {
+            let (axis, angle) = impact.rotation.to_axis_angle();
+            let angle = axis.z * angle;
+            outline
+                .fragments(count, rng)
+                .into_iter()
+                .map(|(offset, piece)| {
+                    let offset = (impact.rotation * offset.extend(0.0)).truncate();
+                    (offset, Some(piece.rotated(angle)))
+                })
+                .collect()
+        }
+        None => (0..count)
+            .map(|_| {
+                let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+                (Vec2::new(angle.cos(), angle.sin()) * 10.0, None)
+            })
+            .collect(),
+    };
+
+    let direction = impact.direction(rng);
+    let half_spread = fragmentation.spread.max(0.0) / 2.0;
+    let (min_speed, max_speed) = fragmentation.speed;
+
+    pieces
+        .into_iter()
+        .map(|(offset, outline)| {
+            let angle = if half_spread > 0.0 {
+                rng.gen_range(-half_spread..half_spread)
+            } else {
+                0.0
+            };
+            let speed = if max_speed > min_speed {
+                rng.gen_range(min_speed..max_speed)
+            } else {
+                min_speed
+            } * speed_factor;
+            let heading = (Quat::from_rotation_z(angle) * direction.extend(0.0)).truncate();
+
+            AsteroidSpawn {
+                asteroid: fragmentation.child,
+                position: impact.position + offset,
+                velocity: impact.velocity * fragmentation.inherit_velocity + heading * speed,
+                spin: rng.gen_range(-5.0..5.0),
+                outline,
+                material: impact.material,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    fn fragmentation() -> Fragmentation {
+        Fragmentation {
+            child: AsteroidClass::Small,
+            count: (2, 4),
+            speed: (50.0, 150.0),
+            spread: std::f32::consts::FRAC_PI_2,
+            inherit_velocity: 0.5,
+        }
+    }
+
+    fn impact() -> Impact<'static> {
+        Impact {
+            position: Vec2::new(100.0, 50.0),
+            velocity: Vec2::new(20.0, -10.0),
+            rotation: Quat::IDENTITY,
+            outline: None,
+            material: None,
+            hit_position: Vec2::new(90.0, 50.0),
+            hit_velocity: Vec2::new(500.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn count_stays_in_range() {
+        let fragmentation = fragmentation();
+        for seed in 0..100 {
+            let pieces = fragment(&fragmentation, &impact(), &mut StdRng::seed_from_u64(seed));
+            assert!((2..=4).contains(&pieces.len()), "{} pieces", pieces.len());
+        }
+    }
+
+    #[test]
+    fn pieces_have_the_child_class() {
+        let pieces = fragment(&fragmentation(), &impact(), &mut StdRng::seed_from_u64(1));
+        assert!(pieces
+            .iter()
+            .all(|piece| piece.asteroid == AsteroidClass::Small));
+    }
+
+    #[test]
+    fn speeds_stay_in_range() {
+        let fragmentation = fragmentation();
+        let impact = impact();
+        for seed in 0..100 {
+            for piece in fragment(&fragmentation, &impact, &mut StdRng::seed_from_u64(seed)) {
+                let own = piece.velocity - impact.velocity * fragmentation.inherit_velocity;
+                let speed = own.length();
+                assert!(
+                    (50.0 - 1e-3..=150.0 + 1e-3).contains(&speed),
+                    "speed {}",
+                    speed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn headings_stay_within_the_spread() {
+        let fragmentation = Fragmentation {
+            inherit_velocity: 0.0,
+            ..fragmentation()
+        };
+        let impact = impact();
+        let direction = impact.direction(&mut StdRng::seed_from_u64(0));
+        for seed in 0..100 {
+            for piece in fragment(&fragmentation, &impact, &mut StdRng::seed_from_u64(seed)) {
+                let angle = piece.velocity.angle_between(direction).abs();
+                assert!(
+                    angle <= fragmentation.spread / 2.0 + 1e-3,
+                    "angle {}",
+                    angle
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parent_velocity_is_inherited() {
+        let fragmentation = Fragmentation {
+            speed: (0.0, 0.0),
+            ..fragmentation()
+        };
+        let impact = impact();
+        for piece in fragment(&fragmentation, &impact, &mut StdRng::seed_from_u64(2)) {
+            assert!((piece.velocity - impact.velocity * 0.5).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn pieces_follow_the_hit() {
+        let fragmentation = Fragmentation {
+            spread: 0.0,
+            inherit_velocity: 0.0,
+            ..fragmentation()
+        };
+        // Hit from the left by a shot going right
+        for piece in fragment(&fragmentation, &impact(), &mut StdRng::seed_from_u64(3)) {
+            let heading = piece.velocity.normalize();
+            assert!((heading - Vec2::X).length() < 1e-3, "heading {}", heading);
+        }
+    }
+}
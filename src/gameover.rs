@@ -0,0 +1,308 @@
+/// Game over
+/// Once the game mode says the game is over, the controls are locked and the
+/// game goes on for `GameOverRules::delay` seconds, letting the last
+/// explosions settle. The game then gives way to the `GameOver` screen,
+/// through the name entry if the score is a high score.
+///
+/// The screen sums the game up from the `GameSummary` taken when it ended,
+/// and offers to continue to the title or to retry from the ship selection.
+use crate::{
+    Acceleration, AppState, ControlLocked, Fire, Firing, GameClock, GameFont, GameMode, HighScores,
+    PlayerControlled, PlayerLifes, Score, Shooter, ShotHit, SpawnMode, Wave,
+};
+use bevy::{
+    app::{AppBuilder, EventReader, Plugin},
+    core::{Time, Timer},
+    ecs::{
+        entity::Entity,
+        query::{Added, With},
+        schedule::{State, SystemSet},
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, Input},
+    math::Rect,
+    render::color::Color,
+    text::{Text, TextSection, TextStyle},
+    ui::{entity::TextBundle, AlignSelf, PositionType, Style, Val},
+};
+
+pub struct GameOverRules {
+    /// Seconds between the end of the game and the game over screen
+    pub delay: f32,
+}
+
+impl Default for GameOverRules {
+    fn default() -> Self {
+        GameOverRules { delay: 2.5 }
+    }
+}
+
+/// Projectiles fired by the player during the current game, and how many
+/// hit something
+#[derive(Default)]
+struct GameStats {
+    shots: u32,
+    hits: u32,
+}
+
+/// The game as it ended
+#[derive(Debug, Clone, Default)]
+pub struct GameSummary {
+    pub mode: GameMode,
+    pub score: u64,
+    /// Wave reached, none out of the waves spawn mode
+    pub wave: Option<u32>,
+    pub shots: u32,
+    pub hits: u32,
+    /// Whether the score beats the best one of the mode
+    pub new_record: bool,
+}
+
+impl GameSummary {
+    /// Part of the shots which hit something, none without shots
+    pub fn accuracy(&self) -> Option<f32> {
+        if self.shots > 0 {
+            Some(self.hits as f32 / self.shots as f32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Time left before the game over screen
+struct Ending(Timer);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Continue,
+    Retry,
+}
+
+impl Choice {
+    fn name(self) -> &'static str {
+        match self {
+            Choice::Continue => "Continue",
+            Choice::Retry => "Retry",
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Choice::Continue => Choice::Retry,
+            Choice::Retry => Choice::Continue,
+        }
+    }
+}
+
+struct GameOverScreen;
+
+fn reset_stats(mut commands: Commands) {
+    commands.insert_resource(GameStats::default());
+}
+
+fn count_shots(
+    mut stats: ResMut<GameStats>,
+    mut hits: EventReader<ShotHit>,
+    q_fires: Query<&Shooter, Added<Fire>>,
+    q_player: Query<(), With<PlayerControlled>>,
+) {
+    for shooter in q_fires.iter() {
+        if q_player.get(shooter.0).is_ok() {
+            stats.shots += 1;
+        }
+    }
+    for hit in hits.iter() {
+        if q_player.get(hit.shooter).is_ok() {
+            stats.hits += 1;
+        }
+    }
+}
+
+/// Locks the controls once the game is over, then takes the summary and
+/// leaves the game when the delay is over
+fn game_over(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<GameOverRules>,
+    mut state: ResMut<State<AppState>>,
+    mode: Res<GameMode>,
+    clock: Res<GameClock>,
+    lifes: Res<PlayerLifes>,
+    score: Res<Score>,
+    spawn_mode: Res<SpawnMode>,
+    wave: Res<Wave>,
+    stats: Res<GameStats>,
+    high_scores: Res<HighScores>,
+    ending: Option<ResMut<Ending>>,
+    mut q_player: Query<(Entity, &mut Acceleration), With<PlayerControlled>>,
+) {
+    let mut ending = match ending {
+        Some(ending) => ending,
+        None => {
+            if mode.is_over(lifes.0, clock.elapsed) {
+                commands.insert_resource(Ending(Timer::from_seconds(rules.delay, false)));
+                for (entity, mut acceleration) in q_player.iter_mut() {
+                    acceleration.forward = 0.0;
+                    commands
+                        .entity(entity)
+                        .insert(ControlLocked)
+                        .remove::<Firing>();
+                }
+            }
+            return;
+        }
+    };
+    if !ending.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    commands.insert_resource(GameSummary {
+        mode: *mode,
+        score: score.current,
+        wave: match *spawn_mode {
+            SpawnMode::Waves => Some(wave.number),
+            SpawnMode::Endless => None,
+        },
+        shots: stats.shots,
+        hits: stats.hits.min(stats.shots),
+        new_record: mode.ranked() && score.current > high_scores.best(*mode),
+    });
+    if high_scores.qualifies(*mode, score.current) {
+        state.set(AppState::NameEntry).unwrap();
+    } else {
+        state.set(AppState::GameOver).unwrap();
+    }
+}
+
+fn remove_ending(mut commands: Commands) {
+    commands.remove_resource::<Ending>();
+}
+
+fn add_game_over_screen(mut commands: Commands, font: Res<GameFont>, summary: Res<GameSummary>) {
+    commands.insert_resource(Choice::Continue);
+
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+
+    let mut details = format!("{}\nScore: {}\n", summary.mode.name(), summary.score);
+    if let Some(wave) = summary.wave {
+        details.push_str(&format!("Wave: {}\n", wave));
+    }
+    match summary.accuracy() {
+        Some(accuracy) => details.push_str(&format!("Accuracy: {:.0}%\n", accuracy * 100.0)),
+        None => details.push_str("Accuracy: -\n"),
+    }
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(35.),
+                    bottom: Val::Percent(30.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "Game over\n".into(),
+                        style: style(120., Color::ORANGE_RED),
+                    },
+                    TextSection {
+                        value: if summary.new_record {
+                            "New record!\n".into()
+                        } else {
+                            String::new()
+                        },
+                        style: style(60., Color::ORANGE),
+                    },
+                    TextSection {
+                        value: details,
+                        style: style(42., Color::WHITE),
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: style(42., Color::BLUE),
+                    },
+                ],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        })
+        .insert(GameOverScreen);
+}
+
+/// Continuing pops back to the title, retrying replaces the screen by the
+/// ship selection
+fn choose(
+    keyboard: Res<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    mut choice: ResMut<Choice>,
+    mut q_text: Query<&mut Text, With<GameOverScreen>>,
+) {
+    if [KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down]
+        .iter()
+        .any(|&key| keyboard.just_pressed(key))
+    {
+        *choice = choice.other();
+    }
+
+    for mut text in q_text.iter_mut() {
+        text.sections[3].value = [Choice::Continue, Choice::Retry]
+            .iter()
+            .map(|&option| {
+                if option == *choice {
+                    format!("> {} <", option.name())
+                } else {
+                    format!("  {}  ", option.name())
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("   ");
+    }
+
+    if keyboard.just_pressed(KeyCode::Return) || keyboard.just_pressed(KeyCode::Space) {
+        match *choice {
+            Choice::Continue => state.pop().unwrap(),
+            Choice::Retry => state.set(AppState::ShipSelect).unwrap(),
+        }
+    }
+}
+
+fn remove_game_over_screen(mut commands: Commands, query: Query<Entity, With<GameOverScreen>>) {
+    commands.remove_resource::<Choice>();
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameOverRules>()
+            .init_resource::<GameSummary>()
+            .init_resource::<GameStats>()
+            .add_system_set(SystemSet::on_enter(AppState::Game).with_system(reset_stats.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(count_shots.system())
+                    .with_system(game_over.system()),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(remove_ending.system()))
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(add_game_over_screen.system()),
+            )
+            .add_system_set(SystemSet::on_update(AppState::GameOver).with_system(choose.system()))
+            .add_system_set(
+                SystemSet::on_exit(AppState::GameOver)
+                    .with_system(remove_game_over_screen.system()),
+            );
+    }
+}
@@ -0,0 +1,109 @@
+/// Health system
+/// Entities holding a `Health` component survive collisions until their hit
+/// points drop to zero. What hits them deals the amount stored in its `Damage`
+/// component.
+///
+/// An `Invulnerable` entity temporarily loses its `CollisionMask` and blinks.
+/// The mask is given back once the timer is over.
+use crate::{AppState, CollisionMask};
+use bevy::{
+    app::{AppBuilder, Plugin},
+    core::{Time, Timer},
+    ecs::{
+        entity::Entity,
+        query::Added,
+        schedule::SystemSet,
+        system::{Commands, IntoSystem, Query, Res},
+    },
+    render::draw::Visible,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Health {
+    pub current: u16,
+    pub max: u16,
+}
+
+impl Health {
+    pub fn new(max: u16) -> Self {
+        Health { current: max, max }
+    }
+
+    /// Removes `amount` hit points, returns true if there are none left
+    pub fn damage(&mut self, amount: u16) -> bool {
+        self.current = self.current.saturating_sub(amount);
+        self.is_dead()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+
+    /// Ratio of lost hit points, from 0.0 (intact) to 1.0 (dead)
+    pub fn wear(&self) -> f32 {
+        if self.max == 0 {
+            1.0
+        } else {
+            1.0 - self.current as f32 / self.max as f32
+        }
+    }
+}
+
+/// Hit points removed from a `Health` colliding with this entity
+#[derive(Debug, Clone, Copy)]
+pub struct Damage(pub u16);
+
+pub struct Invulnerable {
+    duration: Timer,
+    blink: Timer,
+    mask: CollisionMask,
+}
+
+impl Invulnerable {
+    /// `mask` is the `CollisionMask` restored when invulnerability is over
+    pub fn new(seconds: f32, mask: CollisionMask) -> Self {
+        Invulnerable {
+            duration: Timer::from_seconds(seconds, false),
+            blink: Timer::from_seconds(0.08, true),
+            mask,
+        }
+    }
+}
+
+fn new_invulnerability(mut commands: Commands, query: Query<Entity, Added<Invulnerable>>) {
+    for e in query.iter() {
+        commands.entity(e).remove::<CollisionMask>();
+    }
+}
+
+fn remove_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable, &mut Visible)>,
+) {
+    for (e, mut invulnerable, mut visible) in query.iter_mut() {
+        if invulnerable.blink.tick(time.delta()).just_finished() {
+            visible.is_visible = !visible.is_visible;
+        }
+
+        if invulnerable.duration.tick(time.delta()).just_finished() {
+            visible.is_visible = true;
+            commands
+                .entity(e)
+                .remove::<Invulnerable>()
+                .insert(invulnerable.mask);
+        }
+    }
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::Game)
+                .with_system(new_invulnerability.system())
+                .with_system(remove_invulnerability.system()),
+        );
+    }
+}
@@ -0,0 +1,557 @@
+/// High-score table
+/// The best scores of each ranked game mode are kept with the initials of
+/// their player in a small text file of the user data directory: a `version`
+/// line, then one `mode score initials` line per entry, best first. The
+/// version is bumped whenever the format changes, older files being migrated
+/// on load: version 1 had no mode, its scores are classic ones.
+///
+/// A file which cannot be read is moved aside as a `.bak` backup and the
+/// table starts empty. A file written by a newer version of the game is left
+/// alone: the table starts empty and is not saved. Saving failures are
+/// reported and the game goes on.
+///
+/// When a game ends with a score making it into the table, the player enters
+/// their initials on the `NameEntry` screen, before the game over screen.
+use crate::{AppState, GameFont, GameMode, Score};
+use bevy::{
+    app::{AppBuilder, EventReader, Plugin},
+    ecs::{
+        entity::Entity,
+        query::With,
+        schedule::{State, SystemSet},
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, Input},
+    log::warn,
+    math::Rect,
+    render::color::Color,
+    text::{Text, TextSection, TextStyle},
+    ui::{entity::TextBundle, AlignSelf, PositionType, Style, Val},
+    window::ReceivedCharacter,
+};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+const FORMAT_VERSION: u32 = 2;
+const FILE_NAME: &str = "highscores.txt";
+const INITIALS_LENGTH: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighScore {
+    pub initials: String,
+    pub score: u64,
+    pub mode: GameMode,
+}
+
+pub struct HighScores {
+    /// Every mode, best first
+    entries: Vec<HighScore>,
+    /// Entries kept for each mode
+    pub capacity: usize,
+    /// File the table is saved to, none if there is no data directory or if
+    /// the file belongs to a newer version
+    path: Option<PathBuf>,
+}
+
+impl Default for HighScores {
+    fn default() -> Self {
+        HighScores {
+            entries: Vec::new(),
+            capacity: 10,
+            path: None,
+        }
+    }
+}
+
+impl HighScores {
+    /// Table of a mode, best first
+    pub fn entries(&self, mode: GameMode) -> impl Iterator<Item = &HighScore> {
+        self.entries.iter().filter(move |entry| entry.mode == mode)
+    }
+
+    /// Whether the score would make it into the table of the mode
+    pub fn qualifies(&self, mode: GameMode, score: u64) -> bool {
+        if !mode.ranked() || score == 0 {
+            return false;
+        }
+        match self.entries(mode).nth(self.capacity.saturating_sub(1)) {
+            Some(last) => score > last.score,
+            None => self.capacity > 0,
+        }
+    }
+
+    /// Inserts the entry below the equal scores, returns its rank in the
+    /// table of its mode if it made it
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        let mode = entry.mode;
+        if !mode.ranked() {
+            return None;
+        }
+        let rank = self
+            .entries(mode)
+            .position(|other| other.score < entry.score)
+            .unwrap_or_else(|| self.entries(mode).count());
+        if rank >= self.capacity {
+            return None;
+        }
+
+        let index = self
+            .entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(index, entry);
+
+        let capacity = self.capacity;
+        let mut kept = 0;
+        self.entries.retain(|entry| {
+            if entry.mode != mode {
+                return true;
+            }
+            kept += 1;
+            kept <= capacity
+        });
+        Some(rank)
+    }
+
+    pub fn best(&self, mode: GameMode) -> u64 {
+        self.entries(mode).next().map_or(0, |entry| entry.score)
+    }
+
+    /// Loads the table from `path`. A missing file gives an empty table, an
+    /// unreadable one is backed up first.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => match parse(&content) {
+                Ok(entries) => entries,
+                Err(_) if matches!(version(&content), Ok(version) if version > FORMAT_VERSION) => {
+                    warn!(
+                        "High scores in {} come from a newer version, they are left untouched",
+                        path.display()
+                    );
+                    return HighScores::default();
+                }
+                Err(error) => {
+                    warn!("Invalid high scores in {}: {}", path.display(), error);
+                    backup(&path);
+                    Vec::new()
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => {
+                warn!("Cannot read high scores from {}: {}", path.display(), error);
+                backup(&path);
+                Vec::new()
+            }
+        };
+
+        let mut high_scores = HighScores {
+            path: Some(path),
+            ..Default::default()
+        };
+        for entry in entries {
+            high_scores.insert(entry);
+        }
+        high_scores
+    }
+
+    /// Writes the table next to its file before replacing it, so that an
+    /// interrupted save does not lose the previous one
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut content = format!("version {}\n", FORMAT_VERSION);
+        for entry in self.entries.iter() {
+            content.push_str(&format!(
+                "{} {} {}\n",
+                entry.mode.key(),
+                entry.score,
+                entry.initials
+            ));
+        }
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, content)?;
+        fs::rename(&temporary, path)
+    }
+}
+
+/// Format version of the file, from its first line
+fn version(content: &str) -> Result<u32, String> {
+    content
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .and_then(|line| line.trim().strip_prefix("version "))
+        .ok_or_else(|| "missing version".to_string())?
+        .trim()
+        .parse::<u32>()
+        .map_err(|error| format!("bad version: {}", error))
+}
+
+fn parse(content: &str) -> Result<Vec<HighScore>, String> {
+    let version = version(content)?;
+    let lines = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .skip(1);
+
+    match version {
+        1 => lines
+            .map(|line| parse_entry(GameMode::Classic, line.split_whitespace()))
+            .collect(),
+        2 => lines
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                let mode = fields
+                    .next()
+                    .and_then(GameMode::from_key)
+                    .ok_or_else(|| format!("bad mode in {:?}", line))?;
+                parse_entry(mode, fields)
+            })
+            .collect(),
+        _ => Err(format!("unknown version {}", version)),
+    }
+}
+
+/// Parses the `score initials` fields of an entry
+fn parse_entry<'a>(
+    mode: GameMode,
+    mut fields: impl Iterator<Item = &'a str>,
+) -> Result<HighScore, String> {
+    let score = fields
+        .next()
+        .and_then(|score| score.parse().ok())
+        .ok_or_else(|| "bad score".to_string())?;
+    let initials = fields
+        .next()
+        .filter(|initials| initials.chars().count() <= INITIALS_LENGTH)
+        .ok_or_else(|| format!("bad initials for score {}", score))?;
+    if fields.next().is_some() {
+        return Err(format!("trailing data after score {}", score));
+    }
+    Ok(HighScore {
+        initials: initials.to_string(),
+        score,
+        mode,
+    })
+}
+
+/// Moves an unreadable file aside, replacing any previous backup
+fn backup(path: &Path) {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    if let Err(error) = fs::rename(path, &backup) {
+        warn!("Cannot back up {}: {}", path.display(), error);
+    }
+}
+
+/// Directory for the game data: `ASTEROID_DATA_DIR` if set, otherwise the
+/// platform data directory
+fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("ASTEROID_DATA_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.map(|base| base.join("bevy_asteroid"))
+}
+
+fn load_high_scores(mut commands: Commands) {
+    let high_scores = match data_dir() {
+        Some(dir) => HighScores::load(dir.join(FILE_NAME)),
+        None => {
+            warn!("No data directory, high scores will not be saved");
+            HighScores::default()
+        }
+    };
+    commands.insert_resource(high_scores);
+}
+
+/// Initials being typed
+#[derive(Default)]
+struct Initials(String);
+
+struct NameEntry;
+
+fn add_name_entry(mut commands: Commands, font: Res<GameFont>, score: Res<Score>) {
+    commands.insert_resource(Initials::default());
+
+    let style = |size: f32, color: Color| TextStyle {
+        font: font.0.clone(),
+        font_size: size,
+        color,
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(30.),
+                    bottom: Val::Percent(40.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: format!("New high score: {}\n", score.current),
+                        style: style(60., Color::ORANGE_RED),
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: style(90., Color::WHITE),
+                    },
+                    TextSection {
+                        value: "Type your initials, Enter to confirm".into(),
+                        style: style(32., Color::BLUE),
+                    },
+                ],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        })
+        .insert(NameEntry);
+}
+
+fn enter_initials(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut state: ResMut<State<AppState>>,
+    mut initials: ResMut<Initials>,
+    mut high_scores: ResMut<HighScores>,
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    mut q_text: Query<&mut Text, With<NameEntry>>,
+) {
+    for character in characters.iter() {
+        if character.char.is_ascii_alphanumeric() && initials.0.len() < INITIALS_LENGTH {
+            initials.0.push(character.char.to_ascii_uppercase());
+        }
+    }
+    if keyboard.just_pressed(KeyCode::Back) {
+        initials.0.pop();
+    }
+
+    for mut text in q_text.iter_mut() {
+        text.sections[1].value = format!("{:_<1$}\n", initials.0, INITIALS_LENGTH);
+    }
+
+    if keyboard.just_pressed(KeyCode::Return) && !initials.0.is_empty() {
+        high_scores.insert(HighScore {
+            initials: initials.0.clone(),
+            score: score.current,
+            mode: *mode,
+        });
+        if let Err(error) = high_scores.save() {
+            warn!("Cannot save high scores: {}", error);
+        }
+        // Not to be taken as a choice by the game over screen
+        keyboard.reset(KeyCode::Return);
+        state.set(AppState::GameOver).unwrap();
+    }
+}
+
+fn remove_name_entry(mut commands: Commands, query: Query<Entity, With<NameEntry>>) {
+    commands.remove_resource::<Initials>();
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(load_high_scores.system())
+            .add_system_set(
+                SystemSet::on_enter(AppState::NameEntry).with_system(add_name_entry.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::NameEntry).with_system(enter_initials.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::NameEntry).with_system(remove_name_entry.system()),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mode: GameMode, score: u64, initials: &str) -> HighScore {
+        HighScore {
+            initials: initials.to_string(),
+            score,
+            mode,
+        }
+    }
+
+    fn scores(high_scores: &HighScores, mode: GameMode) -> Vec<u64> {
+        high_scores.entries(mode).map(|entry| entry.score).collect()
+    }
+
+    #[test]
+    fn insert_keeps_capacity_entries_per_mode() {
+        let mut high_scores = HighScores {
+            capacity: 2,
+            ..Default::default()
+        };
+        for score in 1..=3 {
+            high_scores.insert(entry(GameMode::Classic, score * 100, "CLA"));
+            high_scores.insert(entry(GameMode::Survival, score * 10, "SUR"));
+        }
+        assert_eq!(scores(&high_scores, GameMode::Classic), vec![300, 200]);
+        assert_eq!(scores(&high_scores, GameMode::Survival), vec![30, 20]);
+
+        // A full table of one mode does not keep another one from filling
+        assert_eq!(
+            high_scores.insert(entry(GameMode::TimeAttack, 1, "TIM")),
+            Some(0)
+        );
+        assert_eq!(
+            high_scores.insert(entry(GameMode::Survival, 25, "SUR")),
+            Some(1)
+        );
+        assert_eq!(scores(&high_scores, GameMode::Survival), vec![30, 25]);
+        assert_eq!(scores(&high_scores, GameMode::Classic), vec![300, 200]);
+        assert_eq!(high_scores.insert(entry(GameMode::Zen, 1000, "ZEN")), None);
+    }
+
+    #[test]
+    fn version_1_scores_are_classic_ones() {
+        let dir = env::temp_dir().join(format!("asteroid_high_scores_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+        fs::write(&path, "version 1\n500 ABC\n200 DE\n").unwrap();
+
+        let high_scores = HighScores::load(path.clone());
+        assert_eq!(
+            high_scores.entries(GameMode::Classic).collect::<Vec<_>>(),
+            vec![
+                &entry(GameMode::Classic, 500, "ABC"),
+                &entry(GameMode::Classic, 200, "DE")
+            ]
+        );
+        high_scores.save().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "version 2\nclassic 500 ABC\nclassic 200 DE\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_reads_every_entry() {
+        assert_eq!(
+            parse("version 2\n\nclassic 500 ABC\nsurvival 20 D\n"),
+            Ok(vec![
+                entry(GameMode::Classic, 500, "ABC"),
+                entry(GameMode::Survival, 20, "D")
+            ])
+        );
+        assert_eq!(parse("version 2\n"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_files() {
+        for content in [
+            "",
+            "classic 500 ABC\n",
+            "version two\n",
+            "version 3\nclassic 500 ABC\n",
+            "version 2\nunknown 500 ABC\n",
+            "version 2\nclassic many ABC\n",
+            "version 2\nclassic 500 ABCD\n",
+            "version 2\nclassic 500\n",
+            "version 2\nclassic 500 ABC DEF\n",
+        ] {
+            assert!(parse(content).is_err(), "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn insert_ranks_below_equal_scores() {
+        let mut high_scores = HighScores {
+            capacity: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            high_scores.insert(entry(GameMode::Classic, 100, "A")),
+            Some(0)
+        );
+        assert_eq!(
+            high_scores.insert(entry(GameMode::Classic, 300, "B")),
+            Some(0)
+        );
+        assert_eq!(
+            high_scores.insert(entry(GameMode::Classic, 100, "C")),
+            Some(2)
+        );
+        assert_eq!(high_scores.insert(entry(GameMode::Classic, 100, "D")), None);
+        assert_eq!(
+            high_scores.insert(entry(GameMode::Classic, 200, "E")),
+            Some(1)
+        );
+        assert_eq!(
+            high_scores
+                .entries(GameMode::Classic)
+                .map(|entry| entry.initials.as_str())
+                .collect::<Vec<_>>(),
+            vec!["B", "E", "A"]
+        );
+    }
+
+    #[test]
+    fn qualifies_for_a_place_in_the_mode_table() {
+        let mut high_scores = HighScores {
+            capacity: 2,
+            ..Default::default()
+        };
+        assert!(high_scores.qualifies(GameMode::Classic, 1));
+        assert!(!high_scores.qualifies(GameMode::Classic, 0));
+        assert!(!high_scores.qualifies(GameMode::Zen, 1000));
+
+        high_scores.insert(entry(GameMode::Classic, 300, "A"));
+        high_scores.insert(entry(GameMode::Classic, 200, "B"));
+        assert!(high_scores.qualifies(GameMode::Classic, 201));
+        assert!(!high_scores.qualifies(GameMode::Classic, 200));
+        assert!(high_scores.qualifies(GameMode::Survival, 1));
+    }
+
+    #[test]
+    fn newer_files_are_left_untouched() {
+        let dir = env::temp_dir().join(format!("asteroid_newer_scores_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+        let content = "version 3\nclassic 500 ABC hard\n";
+        fs::write(&path, content).unwrap();
+
+        let mut high_scores = HighScores::load(path.clone());
+        assert_eq!(high_scores.best(GameMode::Classic), 0);
+        high_scores.insert(entry(GameMode::Classic, 100, "NEW"));
+        high_scores.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        let mut backup = path.into_os_string();
+        backup.push(".bak");
+        assert!(!Path::new(&backup).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
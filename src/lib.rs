@@ -8,41 +8,109 @@ use bevy::{
 
 mod asteroids;
 mod audio;
+mod boss;
+mod camera;
 mod collision;
 mod controls;
 mod fire;
+mod fragmentation;
+mod gameover;
+mod health;
+mod highscores;
+mod missile;
+mod modes;
 mod movement;
+mod outline;
+mod particles;
+mod pause;
 mod player;
+mod pool;
+mod powerups;
 mod rules;
+mod saucer;
 mod score;
+mod ships;
+mod steering;
+mod thruster;
 mod title;
 mod ui;
+mod waves;
 mod wrap;
 
-pub use asteroids::{Asteroid as AsteroidClass, AsteroidsPlugin};
-pub use audio::{AudioChannels, AudioPlugin, SoundEffects};
-pub use collision::{Collider2D, CollisionEvent, CollisionLayer, CollisionMask, CollisionPlugin};
+pub use asteroids::{
+    Asteroid as AsteroidClass, AsteroidDestroyed, AsteroidMaterial, AsteroidsPlugin,
+    Spawn as AsteroidSpawn, SpawnRadius as AsteroidSpawnRadius, SpawnSafety,
+};
+pub use audio::{AudioChannels, AudioPlugin, SoundEffects, Volumes};
+pub use boss::{
+    Boss, BossDefeated, BossKind, BossPhase, BossPlugin, BossRules, BossSegment, BossSpawn,
+};
+pub use camera::{CameraEffect, CameraEffectsPlugin, CameraRules};
+pub use collision::{
+    check as check_collision, Collider2D, CollisionEvent, CollisionLayer, CollisionMask,
+    CollisionPlugin,
+};
 pub use controls::{ControlLocked, ControlsPlugin, PlayerControlled};
-pub use fire::{Fire, FirePlugin, Firing};
-pub use movement::{Acceleration, Friction, MovementPlugin, Thrust, Velocity};
-pub use player::{PlayerPlugin, PlayerTexture};
-pub use rules::{PlayerLifes, RulesPlugin, PLAYER_LIFES_MAX};
+pub use fire::{
+    Aim, Fire, FirePlugin, Firing, Lifetime, Palette, Piercing, Projectile, Shooter, ShotHit,
+    ShotMissed, Spread, Weapon,
+};
+pub use fragmentation::{fragment, Fragmentation, FragmentationTable, Impact};
+pub use gameover::{GameOverPlugin, GameOverRules, GameSummary};
+pub use health::{Damage, Health, HealthPlugin, Invulnerable};
+pub use highscores::{HighScore, HighScores, HighScoresPlugin};
+pub use missile::{missile_thrust, Homing, Missile, MissileBlast, MissilePlugin};
+pub use modes::{GameClock, GameMode, GameModesPlugin};
+pub use movement::{Acceleration, Friction, MovementPlugin, Thrust, TimeScale, Velocity};
+pub use outline::Outline;
+pub use particles::{Emission, ParticleEmitter, ParticleLabel, ParticlesPlugin};
+pub use pause::PausePlugin;
+pub use player::{DamageModel, PlayerHit, PlayerPlugin, PlayerTexture, SafeRespawn, PLAYER_MASK};
+pub use pool::{recycle_or_despawn, Pool, PoolPlugin, Poolable, Pooled, Recycle};
+pub use powerups::{
+    Buffs, DropChance, DropTable, Pickup, PowerUp, PowerUpsPlugin, ScoreShard, Shielded,
+};
+pub use rules::{BonusLifes, LifeGained, PlayerLifes, RulesPlugin, PLAYER_LIFES_MAX};
+pub use saucer::{SaucerPlugin, SaucerRules};
 pub use score::{
-    Score, ScorePlugin, SCORE_BIG_ASTEROID, SCORE_SAUCER, SCORE_SMALL_ASTEROID, SCORE_TINY_ASTEROID,
+    ComboRules, Score, ScorePlugin, SCORE_BIG_ASTEROID, SCORE_BOSS_CORE, SCORE_BOSS_POD,
+    SCORE_GIANT_ROCK, SCORE_SAUCER, SCORE_SMALL_ASTEROID, SCORE_SMALL_SAUCER, SCORE_TINY_ASTEROID,
 };
+pub use ships::{SelectedShip, ShipClass, ShipClasses, ShipsPlugin};
+pub use steering::{Behavior, Steering, SteeringPlugin};
+pub use thruster::{Thruster, ThrusterPlugin};
 pub use title::TitlePlugin;
 pub use ui::{GameFont, UIPlugin};
-pub use wrap::{Ghost, NoWrapProtection, Wrap, WrapCamera, WrapPlugin, Wrapped};
+pub use waves::{SpawnMode, Wave, WaveCleared, WaveRules, WavesPlugin};
+pub use wrap::{wrapped_offset, Ghost, NoWrapProtection, Wrap, WrapCamera, WrapPlugin, Wrapped};
 
 pub struct AsteroidsGamePlugins;
 
 pub const PLAYER: u8 = 0b00000001;
 pub const OBSTACLE: u8 = 0b00000010;
 pub const AMMO: u8 = 0b00000100;
+pub const PICKUP: u8 = 0b00001000;
+pub const ENEMY_AMMO: u8 = 0b00010000;
 
 pub enum Shape2D {
     Rectangle(Vec2),
     Circle(f32),
+    /// Closed outline, points being relative to the entity center
+    Polygon(Vec<Vec2>),
+}
+
+impl Shape2D {
+    /// Radius of a circle containing the shape
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            Shape2D::Rectangle(extends) => extends.length(),
+            Shape2D::Circle(radius) => *radius,
+            Shape2D::Polygon(points) => points
+                .iter()
+                .map(|point| point.length())
+                .fold(0.0, f32::max),
+        }
+    }
 }
 
 impl Default for Shape2D {
@@ -54,7 +122,11 @@ impl Default for Shape2D {
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum AppState {
     Title,
+    ShipSelect,
     Game,
+    NameEntry,
+    Paused,
+    GameOver,
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -79,15 +151,30 @@ impl PluginGroup for AsteroidsGamePlugins {
         group.add(AsteroidsPlugin);
         group.add(AudioPlugin);
         group.add(BasePlugin);
+        group.add(BossPlugin);
+        group.add(CameraEffectsPlugin);
         group.add(CollisionPlugin);
         group.add(ControlsPlugin);
         group.add(FirePlugin);
+        group.add(GameOverPlugin);
+        group.add(HealthPlugin);
+        group.add(HighScoresPlugin);
+        group.add(GameModesPlugin);
+        group.add(MissilePlugin);
         group.add(MovementPlugin);
+        group.add(ParticlesPlugin);
+        group.add(PausePlugin);
         group.add(PlayerPlugin);
+        group.add(PowerUpsPlugin);
         group.add(RulesPlugin);
+        group.add(SaucerPlugin);
         group.add(ScorePlugin);
+        group.add(ShipsPlugin);
+        group.add(SteeringPlugin);
+        group.add(ThrusterPlugin);
         group.add(TitlePlugin);
         group.add(UIPlugin);
+        group.add(WavesPlugin);
         group.add(WrapPlugin);
     }
 }
@@ -0,0 +1,269 @@
+/// Homing missiles
+/// A `Missile` locks on the closest asteroid or saucer, the distance being
+/// measured across the screen edges, and turns towards it using its
+/// `Acceleration`. When its target disappears, a new one is picked.
+///
+/// On impact, a missile detonates: every obstacle within its blast radius
+/// receives a `MissileBlast`, handled as if it was hit by the missile.
+use crate::{
+    wrapped_offset, Acceleration, AppState, AsteroidClass, Collider2D, CollisionEvent,
+    CollisionLayer, Ghost, Thrust, Velocity, WrapCamera, AMMO, OBSTACLE,
+};
+use bevy::{
+    app::{AppBuilder, EventReader, EventWriter, Plugin},
+    ecs::{
+        entity::Entity,
+        query::{With, Without},
+        schedule::SystemSet,
+        system::{IntoSystem, Query},
+    },
+    render::camera::OrthographicProjection,
+    transform::components::Transform,
+};
+use std::collections::HashSet;
+
+/// Steering and blast characteristics of a missile
+#[derive(Debug, Clone, Copy)]
+pub struct Homing {
+    pub thrust: f32,
+    pub turn_rate: f32,
+    pub blast_radius: f32,
+}
+
+pub struct Missile {
+    pub homing: Homing,
+    target: Option<Entity>,
+}
+
+impl Missile {
+    pub fn new(homing: Homing) -> Self {
+        Missile {
+            homing,
+            target: None,
+        }
+    }
+}
+
+/// Blast of a detonated missile reaching an obstacle. Blasts are not sent as
+/// `CollisionEvent`s, which would be taken for new impacts of the missile.
+pub struct MissileBlast {
+    pub obstacle: Entity,
+    pub missile: Entity,
+}
+
+impl MissileBlast {
+    /// The blast as a collision of the obstacle with the missile
+    pub fn collision(&self) -> CollisionEvent {
+        CollisionEvent {
+            source: self.obstacle,
+            target: self.missile,
+            layer: AMMO,
+            contact: None,
+        }
+    }
+}
+
+/// Thrust of a missile from its homing characteristics
+pub fn missile_thrust(homing: &Homing) -> Thrust {
+    Thrust {
+        forward: homing.thrust,
+        backward: 0.0,
+        yaw: homing.turn_rate,
+    }
+}
+
+fn heading(transform: &Transform) -> f32 {
+    let rotation = transform.rotation.to_axis_angle();
+    std::f32::consts::PI / 2.0 + rotation.0.z * rotation.1
+}
+
+fn steer_missiles(
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    mut q_missiles: Query<(
+        &mut Missile,
+        &Transform,
+        &Thrust,
+        &Velocity,
+        &mut Acceleration,
+    )>,
+    q_targets: Query<(Entity, &Transform), With<AsteroidClass>>,
+) {
+    if let Ok(projection) = q_projection.single() {
+        for (mut missile, transform, thrust, velocity, mut acceleration) in q_missiles.iter_mut() {
+            let position = transform.translation.truncate();
+
+            let target = missile
+                .target
+                .and_then(|target| q_targets.get(target).ok())
+                .or_else(|| {
+                    q_targets.iter().min_by(|(_, a), (_, b)| {
+                        let a = wrapped_offset(position, a.translation.truncate(), projection);
+                        let b = wrapped_offset(position, b.translation.truncate(), projection);
+                        a.length_squared()
+                            .partial_cmp(&b.length_squared())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                });
+
+            missile.target = target.map(|(entity, _)| entity);
+            acceleration.forward = thrust.forward;
+            acceleration.rotation = 0.0;
+
+            if let Some((_, target_transform)) = target {
+                let offset = wrapped_offset(
+                    position,
+                    target_transform.translation.truncate(),
+                    projection,
+                );
+                let mut error = offset.y.atan2(offset.x) - heading(transform);
+                while error > std::f32::consts::PI {
+                    error -= std::f32::consts::PI * 2.0;
+                }
+                while error < -std::f32::consts::PI {
+                    error += std::f32::consts::PI * 2.0;
+                }
+
+                // Turn towards the target while damping the current spin
+                acceleration.rotation =
+                    (error * 20.0 - velocity.rotation * 4.0).clamp(-thrust.yaw, thrust.yaw);
+            }
+        }
+    }
+}
+
+fn detonate_missiles(
+    mut events: EventReader<CollisionEvent>,
+    mut blasts: EventWriter<MissileBlast>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_missiles: Query<(&Missile, &Transform)>,
+    q_ghosts: Query<&Ghost>,
+    q_obstacles: Query<(Entity, &Transform, &Collider2D, &CollisionLayer), Without<Ghost>>,
+) {
+    let resolve = |entity: Entity| {
+        q_ghosts
+            .get(entity)
+            .map(|ghost| ghost.target)
+            .unwrap_or(entity)
+    };
+
+    let mut detonated = HashSet::new();
+    for collision in events.iter() {
+        for entity in [collision.source, resolve(collision.target)].iter() {
+            if q_missiles.get(*entity).is_ok() {
+                detonated.insert(*entity);
+            }
+        }
+    }
+
+    if let Ok(projection) = q_projection.single() {
+        for missile_entity in detonated {
+            if let Ok((missile, transform)) = q_missiles.get(missile_entity) {
+                let center = transform.translation.truncate();
+                for (obstacle, obstacle_transform, collider, layer) in q_obstacles.iter() {
+                    if layer.0 & OBSTACLE == 0 {
+                        continue;
+                    }
+
+                    let radius = collider.shape.bounding_radius();
+                    let distance = wrapped_offset(
+                        center,
+                        obstacle_transform.translation.truncate() + collider.position,
+                        projection,
+                    )
+                    .length();
+
+                    if distance < missile.homing.blast_radius + radius {
+                        blasts.send(MissileBlast {
+                            obstacle,
+                            missile: missile_entity,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct MissilePlugin;
+
+impl Plugin for MissilePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<MissileBlast>().add_system_set(
+            SystemSet::on_update(AppState::Game)
+                .with_system(steer_missiles.system())
+                .with_system(detonate_missiles.system()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Shape2D;
+    use bevy::{
+        app::Events,
+        ecs::{
+            schedule::{Stage, SystemStage},
+            world::World,
+        },
+        math::Vec3,
+    };
+
+    #[test]
+    fn one_impact_detonates_once() {
+        let mut world = World::default();
+        world.insert_resource(Events::<CollisionEvent>::default());
+        world.insert_resource(Events::<MissileBlast>::default());
+        world
+            .spawn()
+            .insert(WrapCamera)
+            .insert(OrthographicProjection {
+                left: -400.0,
+                right: 400.0,
+                bottom: -300.0,
+                top: 300.0,
+                ..Default::default()
+            });
+        let homing = Homing {
+            thrust: 100.0,
+            turn_rate: 5.0,
+            blast_radius: 50.0,
+        };
+        let missile = world
+            .spawn()
+            .insert(Missile::new(homing))
+            .insert(Transform::default())
+            .id();
+        let obstacle = world
+            .spawn()
+            .insert(Transform::from_translation(Vec3::new(20.0, 0.0, 0.0)))
+            .insert(Collider2D {
+                shape: Shape2D::Circle(10.0),
+                ..Default::default()
+            })
+            .insert(CollisionLayer(OBSTACLE))
+            .id();
+        world
+            .get_resource_mut::<Events<CollisionEvent>>()
+            .unwrap()
+            .send(CollisionEvent {
+                source: obstacle,
+                target: missile,
+                layer: AMMO,
+                contact: None,
+            });
+
+        let mut stage = SystemStage::parallel();
+        stage.add_system(detonate_missiles.system());
+        stage.run(&mut world);
+        stage.run(&mut world);
+
+        let blasts = world.get_resource::<Events<MissileBlast>>().unwrap();
+        let blasts: Vec<_> = blasts
+            .get_reader()
+            .iter(blasts)
+            .map(|blast| (blast.obstacle, blast.missile))
+            .collect();
+        assert_eq!(blasts, vec![(obstacle, missile)]);
+    }
+}
@@ -0,0 +1,250 @@
+/// Game modes
+/// The `GameMode` picked on the title screen decides how a game goes:
+/// - `Classic`: lives, with waves or endless asteroids as selected
+/// - `TimeAttack`: three minutes to score as much as possible
+/// - `Survival`: a single life, asteroids arriving faster and faster, points
+///   doubled
+/// - `Zen`: the ship cannot be destroyed and nothing is scored, the game is
+///   left from the pause menu
+///
+/// `GameClock` counts the time spent in the current game, driving the time
+/// limit and the survival escalation.
+use crate::{AppState, GameFont, SpawnMode, PLAYER_LIFES_MAX};
+use bevy::{
+    app::{AppBuilder, Plugin},
+    core::Time,
+    ecs::{
+        entity::Entity,
+        query::With,
+        schedule::SystemSet,
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    math::Rect,
+    render::color::Color,
+    text::{Text, TextSection, TextStyle},
+    ui::{entity::TextBundle, AlignSelf, PositionType, Style, Val},
+};
+
+/// Seconds given in time attack
+const TIME_ATTACK_DURATION: f32 = 180.0;
+/// Seconds for the survival spawn rate to increase by the base rate
+const SURVIVAL_ESCALATION: f32 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    Classic,
+    TimeAttack,
+    Survival,
+    Zen,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Classic
+    }
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [
+        GameMode::Classic,
+        GameMode::TimeAttack,
+        GameMode::Survival,
+        GameMode::Zen,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::TimeAttack => "Time Attack",
+            GameMode::Survival => "Survival",
+            GameMode::Zen => "Zen",
+        }
+    }
+
+    /// Name of the mode in saved files
+    pub fn key(self) -> &'static str {
+        match self {
+            GameMode::Classic => "classic",
+            GameMode::TimeAttack => "time_attack",
+            GameMode::Survival => "survival",
+            GameMode::Zen => "zen",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        GameMode::ALL.iter().copied().find(|mode| mode.key() == key)
+    }
+
+    pub fn next(self) -> Self {
+        let index = GameMode::ALL
+            .iter()
+            .position(|&mode| mode == self)
+            .unwrap_or(0);
+        GameMode::ALL[(index + 1) % GameMode::ALL.len()]
+    }
+
+    pub fn previous(self) -> Self {
+        let index = GameMode::ALL
+            .iter()
+            .position(|&mode| mode == self)
+            .unwrap_or(0);
+        GameMode::ALL[(index + GameMode::ALL.len() - 1) % GameMode::ALL.len()]
+    }
+
+    pub fn lifes(self) -> u8 {
+        match self {
+            GameMode::Survival => 1,
+            _ => PLAYER_LIFES_MAX,
+        }
+    }
+
+    /// Whether the ship can be destroyed
+    pub fn mortal(self) -> bool {
+        self != GameMode::Zen
+    }
+
+    pub fn bonus_lifes(self) -> bool {
+        matches!(self, GameMode::Classic | GameMode::TimeAttack)
+    }
+
+    /// Game duration in seconds, if limited
+    pub fn time_limit(self) -> Option<f32> {
+        match self {
+            GameMode::TimeAttack => Some(TIME_ATTACK_DURATION),
+            _ => None,
+        }
+    }
+
+    /// Spawn mode imposed by the game mode, the selected one being used
+    /// otherwise
+    pub fn spawn_mode(self) -> Option<SpawnMode> {
+        match self {
+            GameMode::Survival | GameMode::Zen => Some(SpawnMode::Endless),
+            _ => None,
+        }
+    }
+
+    /// Speed-up of the endless asteroid spawning after `elapsed` seconds
+    pub fn spawn_rate(self, elapsed: f32) -> f32 {
+        match self {
+            GameMode::Survival => 1.0 + elapsed / SURVIVAL_ESCALATION,
+            _ => 1.0,
+        }
+    }
+
+    /// Factor applied to every point scored
+    pub fn score_factor(self) -> u32 {
+        match self {
+            GameMode::Survival => 2,
+            GameMode::Zen => 0,
+            _ => 1,
+        }
+    }
+
+    /// Whether the mode keeps high scores
+    pub fn ranked(self) -> bool {
+        self.score_factor() > 0
+    }
+
+    pub fn is_over(self, lifes: u8, elapsed: f32) -> bool {
+        (self.mortal() && lifes == 0)
+            || matches!(self.time_limit(), Some(limit) if elapsed >= limit)
+    }
+}
+
+/// Time spent in the current game, in seconds
+#[derive(Default)]
+pub struct GameClock {
+    pub elapsed: f32,
+}
+
+/// Time left in a time-limited game
+struct Countdown;
+
+fn enter(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut clock: ResMut<GameClock>,
+    font: Res<GameFont>,
+) {
+    clock.elapsed = 0.0;
+
+    if mode.time_limit().is_some() {
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::Center,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Val::Percent(47.),
+                        top: Val::Px(10.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text {
+                    sections: vec![TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 48.,
+                            color: Color::WHITE,
+                        },
+                    }],
+                    alignment: Default::default(),
+                },
+                ..Default::default()
+            })
+            .insert(Countdown);
+    }
+}
+
+fn tick_clock(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    mut clock: ResMut<GameClock>,
+    mut q_countdown: Query<&mut Text, With<Countdown>>,
+) {
+    clock.elapsed += time.delta_seconds();
+
+    if let Some(limit) = mode.time_limit() {
+        let left = (limit - clock.elapsed).max(0.0).ceil() as u32;
+        for mut text in q_countdown.iter_mut() {
+            text.sections[0].value = format!("{}:{:02}", left / 60, left % 60);
+            text.sections[0].style.color = if left <= 10 {
+                Color::ORANGE_RED
+            } else {
+                Color::WHITE
+            };
+        }
+    }
+}
+
+fn exit(mut commands: Commands, query: Query<Entity, With<Countdown>>) {
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+/// Applies the spawn mode imposed by the game mode before the ship selection
+fn impose_spawn_mode(mode: Res<GameMode>, mut spawn_mode: ResMut<SpawnMode>) {
+    if let Some(imposed) = mode.spawn_mode() {
+        *spawn_mode = imposed;
+    }
+}
+
+pub struct GameModesPlugin;
+
+impl Plugin for GameModesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameMode>()
+            .init_resource::<GameClock>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::ShipSelect).with_system(impose_spawn_mode.system()),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Game).with_system(enter.system()))
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(exit.system()))
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(tick_clock.system()));
+    }
+}
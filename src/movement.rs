@@ -1,7 +1,11 @@
+use crate::{pause::running, CollisionLayer, OBSTACLE};
 use bevy::{
     app::{AppBuilder, Plugin},
     core::Time,
-    ecs::system::{IntoSystem, Query, Res},
+    ecs::{
+        schedule::SystemSet,
+        system::{IntoSystem, Query, Res},
+    },
     math::{Quat, Vec2},
     transform::components::Transform,
 };
@@ -38,13 +42,31 @@ impl Velocity {
 #[derive(Default, Debug)]
 pub struct Friction(pub f32);
 
+/// Speed of time for moving entities.
+/// `global` applies to everything, `hostile` only to entities on the
+/// `OBSTACLE` layer.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeScale {
+    pub global: f32,
+    pub hostile: f32,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale {
+            global: 1.0,
+            hostile: 1.0,
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct Acceleration {
     pub forward: f32,
     pub rotation: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Thrust {
     pub forward: f32,
     pub backward: f32,
@@ -74,18 +96,29 @@ pub fn floor_velocity(mut query: Query<&mut Velocity>) {
     }
 }
 
-pub fn velocity(time: Res<Time>, mut query: Query<(&mut Velocity, &mut Transform)>) {
-    let delta_time = f32::min(0.2, time.delta_seconds());
+pub fn velocity(
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<(&mut Velocity, &mut Transform, Option<&CollisionLayer>)>,
+) {
+    for (velocity, mut transform, layer) in query.iter_mut() {
+        let mut delta_time = f32::min(0.2, time.delta_seconds()) * time_scale.global;
+        if matches!(layer, Some(layer) if layer.0 & OBSTACLE != 0) {
+            delta_time *= time_scale.hostile;
+        }
 
-    for (velocity, mut transform) in query.iter_mut() {
         transform.rotate(Quat::from_rotation_z(velocity.rotation * delta_time));
         transform.translation.x += velocity.translation.x * delta_time;
         transform.translation.y += velocity.translation.y * delta_time;
     }
 }
 
-pub fn acceleration(time: Res<Time>, mut query: Query<(&Acceleration, &Transform, &mut Velocity)>) {
-    let delta_time = f32::min(0.2, time.delta_seconds());
+pub fn acceleration(
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<(&Acceleration, &Transform, &mut Velocity)>,
+) {
+    let delta_time = f32::min(0.2, time.delta_seconds()) * time_scale.global;
 
     for (acceleration, transform, mut velocity) in query.iter_mut() {
         velocity.rotation += acceleration.rotation * delta_time;
@@ -99,8 +132,12 @@ pub fn acceleration(time: Res<Time>, mut query: Query<(&Acceleration, &Transform
     }
 }
 
-pub fn friction(time: Res<Time>, mut query: Query<(&Friction, &mut Velocity)>) {
-    let delta_time = f32::min(0.2, time.delta_seconds());
+pub fn friction(
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<(&Friction, &mut Velocity)>,
+) {
+    let delta_time = f32::min(0.2, time.delta_seconds()) * time_scale.global;
     for (friction, mut velocity) in query.iter_mut() {
         velocity.rotation *= 1.0 - (2.0 * friction.0 * delta_time).clamp(0.0, 1.0);
         velocity.translation *= 1.0 - (friction.0 * delta_time).clamp(0.0, 1.0);
@@ -111,9 +148,13 @@ pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(acceleration.system())
-            .add_system(floor_velocity.system())
-            .add_system(velocity.system())
-            .add_system(friction.system());
+        app.init_resource::<TimeScale>().add_system_set(
+            SystemSet::new()
+                .with_run_criteria(running.system())
+                .with_system(acceleration.system())
+                .with_system(floor_velocity.system())
+                .with_system(velocity.system())
+                .with_system(friction.system()),
+        );
     }
 }
@@ -0,0 +1,145 @@
+/// Procedural asteroid outlines
+/// An `Outline` is a jagged loop of points generated from a seed, with a few
+/// deep notches making some of them concave. It is rendered as a line loop
+/// mesh for a vector look, and used as a polygon collider.
+///
+/// Fragments of an outline are wedges cut from its center, so the pieces of a
+/// destroyed asteroid fit together.
+use bevy::{
+    math::{Quat, Vec2},
+    render::{mesh::Mesh, pipeline::PrimitiveTopology},
+};
+use rand::{prelude::*, rngs::StdRng};
+
+#[derive(Debug, Clone)]
+pub struct Outline {
+    /// Points relative to the entity center, in pixels
+    pub points: Vec<Vec2>,
+}
+
+impl Outline {
+    /// Generates an outline fitting in a circle of the given radius
+    pub fn generate(seed: u64, radius: f32) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let count = rng.gen_range(9..15);
+        let step = std::f32::consts::PI * 2.0 / count as f32;
+
+        let points = (0..count)
+            .map(|i| {
+                let angle = (i as f32 + rng.gen_range(-0.3..0.3)) * step;
+                let distance = if rng.gen_bool(0.2) {
+                    rng.gen_range(0.45..0.65)
+                } else {
+                    rng.gen_range(0.8..1.0)
+                } * radius;
+                Vec2::new(angle.cos(), angle.sin()) * distance
+            })
+            .collect();
+
+        Outline { points }
+    }
+
+    /// Regular polygon, first point on the X axis
+    pub fn regular(sides: usize, radius: f32) -> Self {
+        let step = std::f32::consts::PI * 2.0 / sides.max(3) as f32;
+        Outline {
+            points: (0..sides.max(3))
+                .map(|i| Vec2::new((i as f32 * step).cos(), (i as f32 * step).sin()) * radius)
+                .collect(),
+        }
+    }
+
+    /// Distance from the center to the farthest point
+    pub fn radius(&self) -> f32 {
+        self.points
+            .iter()
+            .map(|point| point.length())
+            .fold(0.0, f32::max)
+    }
+
+    pub fn rotated(&self, angle: f32) -> Self {
+        let rotation = Quat::from_rotation_z(angle);
+        Outline {
+            points: self
+                .points
+                .iter()
+                .map(|point| (rotation * point.extend(0.0)).truncate())
+                .collect(),
+        }
+    }
+
+    /// Moves the points so that their bounding box is centered on the origin,
+    /// returns the previous position of that center
+    fn recenter(&mut self) -> Vec2 {
+        let (min, max) = self.points.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), point| (min.min(*point), max.max(*point)),
+        );
+        let center = (min + max) / 2.0;
+        for point in self.points.iter_mut() {
+            *point -= center;
+        }
+        center
+    }
+
+    /// Cuts the outline into `count` wedges from its center.
+    /// Returns each piece with its center position relative to the parent.
+    pub fn fragments(&self, count: usize, rng: &mut impl Rng) -> Vec<(Vec2, Outline)> {
+        let len = self.points.len();
+        let count = count.clamp(1, (len / 2).max(1));
+
+        // Cut lines go from the center to a vertex, with a jagged point midway
+        let center = Vec2::new(rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1)) * self.radius();
+        let offset = rng.gen_range(0..len);
+        let cuts: Vec<(usize, Vec2)> = (0..count)
+            .map(|i| {
+                let index = (offset + i * len / count) % len;
+                let point = self.points[index];
+                let middle = center.lerp(point, rng.gen_range(0.35..0.65))
+                    + point.perp().normalize_or_zero() * self.radius() * rng.gen_range(-0.1..0.1);
+                (index, middle)
+            })
+            .collect();
+
+        (0..count)
+            .map(|i| {
+                let (start, start_middle) = cuts[i];
+                let (end, end_middle) = cuts[(i + 1) % count];
+                let arc = if end > start {
+                    end - start
+                } else {
+                    end + len - start
+                };
+
+                let mut points = vec![center, start_middle];
+                points.extend((0..=arc).map(|j| self.points[(start + j) % len]));
+                points.push(end_middle);
+
+                let mut fragment = Outline { points };
+                let position = fragment.recenter();
+                (position, fragment)
+            })
+            .collect()
+    }
+
+    /// Line loop mesh of the outline, scaled down so that `size` pixels fit in
+    /// the unit square used by sprites
+    pub fn mesh(&self, size: f32) -> Mesh {
+        let mut positions: Vec<[f32; 3]> = self
+            .points
+            .iter()
+            .map(|point| (*point / size).extend(0.0).into())
+            .collect();
+        if let Some(&first) = positions.first() {
+            positions.push(first);
+        }
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+}
@@ -0,0 +1,573 @@
+/// Particles
+/// A `ParticleEmitter` emits particles in bursts or continuously. Particles
+/// are not entities: each emitter keeps them in a fixed pool of slots, reused
+/// as particles die, and draws them all with a single mesh whose vertices
+/// carry their colors.
+///
+/// Particles can also hit obstacles: with a `collision` layer, a particle
+/// touching an entity whose `CollisionMask` includes that layer dies and sends
+/// a `CollisionEvent` targeting the emitter, with the particle as contact.
+///
+/// Emitters are pooled: done emitters are released with their mesh, which is
+/// rebuilt when the entity is acquired for another emitter.
+use crate::{
+    check_collision, recycle_or_despawn, AppState, Collider2D, CollisionEvent, CollisionMask, Pool,
+    PoolPlugin, Poolable, Recycle, Shape2D, TimeScale,
+};
+use bevy::{
+    app::{AppBuilder, EventWriter, Plugin},
+    asset::{Assets, Handle},
+    core::Time,
+    diagnostic::DiagnosticId,
+    ecs::{
+        entity::Entity,
+        query::{Added, With},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel, SystemSet},
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+        world::EntityMut,
+    },
+    math::Vec2,
+    render::{
+        color::Color,
+        draw::Visible,
+        entity::MeshBundle,
+        mesh::Mesh,
+        pipeline::{
+            CompareFunction, CullMode, PipelineDescriptor, PrimitiveTopology, RenderPipeline,
+            RenderPipelines,
+        },
+        shader::{Shader, ShaderStage, ShaderStages},
+    },
+    transform::components::Transform,
+};
+use rand::prelude::*;
+
+const VERTEX_SHADER: &str = r#"
+#version 450
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in vec4 Vertex_Color;
+layout(location = 0) out vec4 v_Color;
+
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+
+void main() {
+    gl_Position = ViewProj * Model * vec4(Vertex_Position, 1.0);
+    v_Color = Vertex_Color;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 450
+layout(location = 0) in vec4 v_Color;
+layout(location = 0) out vec4 o_Target;
+
+void main() {
+    o_Target = v_Color;
+}
+"#;
+
+const ATTRIBUTE_COLOR: &str = "Vertex_Color";
+const VERTICES_PER_PARTICLE: usize = 6;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Emission {
+    /// Emits every particle at once
+    Burst(u16),
+    /// Emits `rate` particles per second, forever or for `duration` seconds
+    Continuous { rate: f32, duration: Option<f32> },
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    pub emission: Emission,
+    /// Particles appear in a disk of this radius around the emitter
+    pub radius: f32,
+    /// Direction and half angle, in radians, of the cone particles head in,
+    /// all around if none
+    pub cone: Option<(Vec2, f32)>,
+    /// Speed range, away from the emitter
+    pub speed: (f32, f32),
+    /// Extra speed per pixel from the emitter center
+    pub radial_speed: f32,
+    /// Velocity shared by all particles, usually the one of the source
+    pub velocity: Vec2,
+    /// Lifetime range, in seconds
+    pub lifetime: (f32, f32),
+    /// Each particle picks one of these colors
+    pub palette: Vec<Color>,
+    /// Tint applied to the particle color from birth to death, evenly spaced
+    pub color_over_life: Vec<Color>,
+    /// Size range, in pixels
+    pub size: (f32, f32),
+    /// Size factor at birth and at death
+    pub size_over_life: (f32, f32),
+    /// Ratio of the velocity lost each second
+    pub drag: f32,
+    /// Layer particles collide on, if any
+    pub collision: Option<u8>,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        ParticleEmitter {
+            emission: Emission::Burst(100),
+            radius: 0.0,
+            cone: None,
+            speed: (0.0, 100.0),
+            radial_speed: 0.0,
+            velocity: Vec2::ZERO,
+            lifetime: (1.0, 1.0),
+            palette: vec![Color::WHITE],
+            color_over_life: vec![Color::WHITE, Color::rgba(1.0, 1.0, 1.0, 0.0)],
+            size: (1.0, 3.0),
+            size_over_life: (1.0, 1.0),
+            drag: 0.0,
+            collision: None,
+        }
+    }
+}
+
+impl Poolable for ParticleEmitter {
+    const NAME: &'static str = "particle_emitter";
+    const SIZE: DiagnosticId = DiagnosticId::from_u128(0x9a43e0c7_5d2b_4f18_8e6c_1b07d4a95f32);
+    const MISSES: DiagnosticId = DiagnosticId::from_u128(0x41f6b8d2_c37a_4a05_9d1e_e82c6059b7a4);
+
+    fn reset(entity: &mut EntityMut) {
+        entity.remove::<ParticleEmitter>();
+        entity.remove::<Particles>();
+    }
+}
+
+impl ParticleEmitter {
+    /// Debris of a destroyed asteroid, faster on the outside
+    pub fn debris(palette: Vec<Color>, scale: f32, velocity: Vec2) -> Self {
+        ParticleEmitter {
+            emission: Emission::Burst((200.0 * scale) as u16),
+            radius: 32.0 * scale,
+            speed: (0.0, 0.0),
+            radial_speed: 1.0,
+            velocity,
+            lifetime: (1.5, 3.0),
+            palette,
+            ..Default::default()
+        }
+    }
+
+    /// Blast of a destroyed ship or saucer. Its particles destroy obstacles.
+    pub fn blast(palette: Vec<Color>, velocity: Vec2, layer: u8) -> Self {
+        ParticleEmitter {
+            emission: Emission::Burst(500),
+            radius: 32.0,
+            speed: (0.0, 0.0),
+            radial_speed: 50.0,
+            velocity,
+            lifetime: (0.6, 1.2),
+            palette,
+            drag: 0.5,
+            collision: Some(layer),
+            ..Default::default()
+        }
+    }
+
+    /// Short-lived sparks of a deflected hit
+    pub fn sparks(palette: Vec<Color>, velocity: Vec2) -> Self {
+        ParticleEmitter {
+            emission: Emission::Burst(12),
+            speed: (150.0, 300.0),
+            velocity,
+            lifetime: (0.2, 0.5),
+            palette,
+            size: (2.0, 2.0),
+            size_over_life: (1.0, 0.3),
+            drag: 2.0,
+            ..Default::default()
+        }
+    }
+
+    /// Slots needed to never run out of particles
+    fn capacity(&self) -> usize {
+        match self.emission {
+            Emission::Burst(count) => count as usize,
+            Emission::Continuous { rate, .. } => (rate * self.lifetime.1).ceil() as usize + 1,
+        }
+    }
+
+    fn emit(&self, origin: Vec2, rng: &mut impl Rng) -> Particle {
+        let angle = match self.cone {
+            Some((direction, spread)) if spread > 0.0 => {
+                direction.y.atan2(direction.x) + rng.gen_range(-spread..spread)
+            }
+            Some((direction, _)) => direction.y.atan2(direction.x),
+            None => rng.gen_range(0.0..std::f32::consts::PI * 2.0),
+        };
+        let direction = Vec2::new(angle.cos(), angle.sin());
+        let far = if self.radius > 0.0 {
+            rng.gen_range(0.0..self.radius)
+        } else {
+            0.0
+        };
+
+        Particle {
+            position: origin + direction * far,
+            velocity: self.velocity
+                + direction * (range(rng, self.speed) + far * self.radial_speed),
+            age: 0.0,
+            lifetime: range(rng, self.lifetime).max(f32::EPSILON),
+            color: self.palette.choose(rng).copied().unwrap_or(Color::WHITE),
+            size: range(rng, self.size),
+        }
+    }
+
+    fn color(&self, particle: &Particle) -> Color {
+        let tint = gradient(&self.color_over_life, particle.age / particle.lifetime);
+        let (color, tint) = (particle.color.as_rgba_f32(), tint.as_rgba_f32());
+        Color::rgba(
+            color[0] * tint[0],
+            color[1] * tint[1],
+            color[2] * tint[2],
+            color[3] * tint[3],
+        )
+    }
+
+    fn size(&self, particle: &Particle) -> f32 {
+        let (birth, death) = self.size_over_life;
+        particle.size * (birth + (death - birth) * particle.age / particle.lifetime)
+    }
+}
+
+fn range(rng: &mut impl Rng, (min, max): (f32, f32)) -> f32 {
+    if max > min {
+        rng.gen_range(min..max)
+    } else {
+        min
+    }
+}
+
+/// Color at `ratio` of evenly spaced colors
+fn gradient(colors: &[Color], ratio: f32) -> Color {
+    match colors.len() {
+        0 => Color::WHITE,
+        1 => colors[0],
+        len => {
+            let position = ratio.clamp(0.0, 1.0) * (len - 1) as f32;
+            let index = (position.floor() as usize).min(len - 2);
+            let (from, to) = (colors[index].as_rgba_f32(), colors[index + 1].as_rgba_f32());
+            let t = position - index as f32;
+            Color::rgba(
+                from[0] + (to[0] - from[0]) * t,
+                from[1] + (to[1] - from[1]) * t,
+                from[2] + (to[2] - from[2]) * t,
+                from[3] + (to[3] - from[3]) * t,
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    color: Color,
+    size: f32,
+}
+
+impl Particle {
+    fn is_alive(&self) -> bool {
+        self.age < self.lifetime
+    }
+}
+
+/// Particle slots of an emitter, dead ones being reused first
+struct Particles {
+    capacity: usize,
+    slots: Vec<Particle>,
+    free: Vec<usize>,
+    pending: f32,
+    elapsed: f32,
+    emitted: bool,
+}
+
+impl Particles {
+    fn new(capacity: usize) -> Self {
+        Particles {
+            capacity,
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            pending: 0.0,
+            elapsed: 0.0,
+            emitted: false,
+        }
+    }
+
+    /// Stores a particle, unless the pool is full
+    fn push(&mut self, particle: Particle) {
+        if let Some(index) = self.free.pop() {
+            self.slots[index] = particle;
+        } else if self.slots.len() < self.capacity {
+            self.slots.push(particle);
+        }
+    }
+
+    fn kill(&mut self, index: usize) {
+        self.slots[index].age = self.slots[index].lifetime;
+        self.free.push(index);
+    }
+
+    fn alive(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+}
+
+struct ParticlePipeline(Handle<PipelineDescriptor>);
+
+/// Quads of the living particles relative to the emitter, dead ones being
+/// degenerated. The vertex count only depends on the pool capacity.
+fn build_mesh(mesh: &mut Mesh, emitter: &ParticleEmitter, particles: &Particles, origin: Vec2) {
+    let capacity = particles.capacity;
+    let mut positions = Vec::with_capacity(capacity * VERTICES_PER_PARTICLE);
+    let mut colors = Vec::with_capacity(capacity * VERTICES_PER_PARTICLE);
+
+    for index in 0..capacity {
+        match particles
+            .slots
+            .get(index)
+            .filter(|particle| particle.is_alive())
+        {
+            Some(particle) => {
+                let center = particle.position - origin;
+                let half = emitter.size(particle) / 2.0;
+                let color = emitter.color(particle).as_linear_rgba_f32();
+                for corner in [
+                    Vec2::new(-half, -half),
+                    Vec2::new(half, -half),
+                    Vec2::new(half, half),
+                    Vec2::new(-half, -half),
+                    Vec2::new(half, half),
+                    Vec2::new(-half, half),
+                ]
+                .iter()
+                {
+                    positions.push([center.x + corner.x, center.y + corner.y, 0.0]);
+                    colors.push(color);
+                }
+            }
+            None => {
+                for _ in 0..VERTICES_PER_PARTICLE {
+                    positions.push([0.0, 0.0, 0.0]);
+                    colors.push([0.0, 0.0, 0.0, 0.0]);
+                }
+            }
+        }
+    }
+
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(ATTRIBUTE_COLOR, colors);
+}
+
+/// Gives new emitters their particle pool and their mesh, reusing the mesh
+/// of pooled entities
+fn attach_meshes(
+    mut commands: Commands,
+    pipeline: Res<ParticlePipeline>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    query: Query<
+        (
+            Entity,
+            &ParticleEmitter,
+            Option<&Transform>,
+            Option<&Handle<Mesh>>,
+        ),
+        Added<ParticleEmitter>,
+    >,
+) {
+    for (entity, emitter, transform, mesh) in query.iter() {
+        let particles = Particles::new(emitter.capacity());
+        let transform = transform.copied().unwrap_or_default();
+        let origin = transform.translation.truncate();
+        let mesh = match mesh.filter(|mesh| meshes.get(*mesh).is_some()) {
+            Some(mesh) => {
+                if let Some(reused) = meshes.get_mut(mesh) {
+                    build_mesh(reused, emitter, &particles, origin);
+                }
+                mesh.clone()
+            }
+            None => {
+                let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+                build_mesh(&mut mesh, emitter, &particles, origin);
+                meshes.add(mesh)
+            }
+        };
+
+        commands
+            .entity(entity)
+            .insert_bundle(MeshBundle {
+                mesh,
+                render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                    pipeline.0.clone(),
+                )]),
+                visible: Visible {
+                    is_transparent: true,
+                    ..Default::default()
+                },
+                transform,
+                ..Default::default()
+            })
+            .insert(particles);
+    }
+}
+
+/// Emits, moves, collides and ages particles, then rebuilds the meshes.
+/// Emitters are released once done emitting and every particle is dead.
+fn update_emitters(
+    mut commands: Commands,
+    pool: Res<Pool<ParticleEmitter>>,
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut events: EventWriter<CollisionEvent>,
+    q_obstacles: Query<(Entity, &Collider2D, &CollisionMask, &Transform)>,
+    mut query: Query<(
+        Entity,
+        &ParticleEmitter,
+        &mut Particles,
+        &Transform,
+        &Handle<Mesh>,
+    )>,
+) {
+    let delta = time.delta_seconds() * time_scale.global;
+    let mut rng = thread_rng();
+
+    for (entity, emitter, mut particles, transform, mesh) in query.iter_mut() {
+        let origin = transform.translation.truncate();
+
+        // Emission
+        let count = match emitter.emission {
+            Emission::Burst(count) if !particles.emitted => {
+                particles.emitted = true;
+                count as usize
+            }
+            Emission::Continuous { rate, duration } => {
+                particles.elapsed += delta;
+                if matches!(duration, Some(duration) if particles.elapsed > duration) {
+                    particles.emitted = true;
+                    0
+                } else {
+                    particles.pending += rate * delta;
+                    let count = particles.pending.floor();
+                    particles.pending -= count;
+                    count as usize
+                }
+            }
+            _ => 0,
+        };
+        for _ in 0..count {
+            let particle = emitter.emit(origin, &mut rng);
+            particles.push(particle);
+        }
+
+        // Simulation
+        let drag = (1.0 - emitter.drag * delta).max(0.0);
+        for index in 0..particles.slots.len() {
+            let particle = &mut particles.slots[index];
+            if !particle.is_alive() {
+                continue;
+            }
+            particle.age += delta;
+            particle.velocity *= drag;
+            particle.position += particle.velocity * delta;
+
+            if !particle.is_alive() {
+                particles.free.push(index);
+                continue;
+            }
+
+            if let Some(layer) = emitter.collision {
+                let collider = Collider2D {
+                    shape: Shape2D::Rectangle(Vec2::splat(particle.size / 2.0)),
+                    ..Default::default()
+                };
+                let particle_transform = Transform::from_translation(particle.position.extend(0.0));
+                let hit = q_obstacles
+                    .iter()
+                    .find(|(_, obstacle, mask, obstacle_transform)| {
+                        mask.0 & layer > 0
+                            && check_collision(
+                                obstacle,
+                                obstacle_transform,
+                                &collider,
+                                &particle_transform,
+                            )
+                    });
+                if let Some((obstacle, ..)) = hit {
+                    events.send(CollisionEvent {
+                        source: obstacle,
+                        target: entity,
+                        layer,
+                        contact: Some((particle.position, particle.velocity)),
+                    });
+                    particles.kill(index);
+                }
+            }
+        }
+
+        if particles.emitted && particles.alive() == 0 {
+            pool.release(&mut commands, entity);
+            continue;
+        }
+
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            build_mesh(mesh, emitter, &particles, origin);
+        }
+    }
+}
+
+fn prepare_resources(
+    mut commands: Commands,
+    mut pipelines: ResMut<Assets<PipelineDescriptor>>,
+    mut shaders: ResMut<Assets<Shader>>,
+) {
+    let mut descriptor = PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
+        fragment: Some(shaders.add(Shader::from_glsl(ShaderStage::Fragment, FRAGMENT_SHADER))),
+    });
+    descriptor.primitive.cull_mode = CullMode::None;
+    if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+        depth_stencil.depth_write_enabled = false;
+        depth_stencil.depth_compare = CompareFunction::LessEqual;
+    }
+    commands.insert_resource(ParticlePipeline(pipelines.add(descriptor)));
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub enum ParticleLabel {
+    /// New emitters get their particles, sized after their emission
+    AttachMeshes,
+}
+
+fn exit(mut commands: Commands, query: Query<(Entity, Option<&Recycle>), With<ParticleEmitter>>) {
+    for (e, recycle) in query.iter() {
+        recycle_or_despawn(&mut commands, e, recycle);
+    }
+}
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(PoolPlugin::<ParticleEmitter>::default())
+            .add_startup_system(prepare_resources.system())
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(exit.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(attach_meshes.system().label(ParticleLabel::AttachMeshes))
+                    .with_system(update_emitters.system()),
+            );
+    }
+}
@@ -0,0 +1,309 @@
+/// Pause
+/// Escape or P during a game pushes `AppState::Paused` on top of `Game`. The
+/// gameplay systems only run on update of `Game`, so they stop along with
+/// their timers; the few systems running in every state, like movement, use
+/// the `running` run criteria. The music and the sound effect channels are
+/// paused too.
+///
+/// The overlay menu resumes the game, restarts it from the ship selection,
+/// sets the volumes or quits to the title. As the state stack takes a single
+/// operation at a time, leaving the game is done once back in `Game`.
+use crate::{AppState, AudioChannels, GameFont, Volumes};
+use bevy::{
+    app::{AppBuilder, Plugin},
+    asset::{Assets, Handle},
+    ecs::{
+        entity::Entity,
+        query::With,
+        schedule::{ShouldRun, State, SystemSet},
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, Input},
+    math::Size,
+    render::color::Color,
+    sprite::ColorMaterial,
+    text::{Text, TextSection, TextStyle},
+    transform::hierarchy::BuildChildren,
+    ui::{
+        entity::{NodeBundle, TextBundle},
+        AlignItems, JustifyContent, PositionType, Style, Val,
+    },
+};
+use bevy_kira_audio::Audio;
+
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Resume,
+    Restart,
+    Settings,
+    Quit,
+}
+
+impl Entry {
+    const ALL: [Entry; 4] = [Entry::Resume, Entry::Restart, Entry::Settings, Entry::Quit];
+
+    fn name(self) -> &'static str {
+        match self {
+            Entry::Resume => "Resume",
+            Entry::Restart => "Restart",
+            Entry::Settings => "Settings",
+            Entry::Quit => "Quit to title",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Setting {
+    Music,
+    Effects,
+    Back,
+}
+
+impl Setting {
+    const ALL: [Setting; 3] = [Setting::Music, Setting::Effects, Setting::Back];
+
+    fn describe(self, volumes: &Volumes) -> String {
+        let percent = |volume: f32| (volume * 100.0).round() as u32;
+        match self {
+            Setting::Music => format!("Music < {}% >", percent(volumes.music)),
+            Setting::Effects => format!("Effects < {}% >", percent(volumes.effects)),
+            Setting::Back => "Back".into(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PauseMenu {
+    settings: bool,
+    selected: usize,
+}
+
+/// How the game is left once resumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Leave {
+    Restart,
+    Quit,
+}
+
+#[derive(Default)]
+struct Leaving(Option<Leave>);
+
+struct Backdrop(Handle<ColorMaterial>);
+
+struct PauseOverlay;
+
+/// Run criteria of the systems running in every state but `Paused`
+pub(crate) fn running(state: Res<State<AppState>>) -> ShouldRun {
+    if *state.current() == AppState::Paused {
+        ShouldRun::No
+    } else {
+        ShouldRun::Yes
+    }
+}
+
+fn prepare_resources(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(Backdrop(
+        materials.add(Color::rgba(0.0, 0.0, 0.0, 0.6).into()),
+    ));
+}
+
+/// Keys are reset once handled, so that the state entered on the same frame
+/// does not handle them again. A game ending on this frame is not paused.
+fn pause(mut keyboard: ResMut<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    for key in [KeyCode::Escape, KeyCode::P] {
+        if keyboard.just_pressed(key) {
+            keyboard.reset(key);
+            let _ = state.push(AppState::Paused);
+            return;
+        }
+    }
+}
+
+fn add_overlay(
+    mut commands: Commands,
+    font: Res<GameFont>,
+    backdrop: Res<Backdrop>,
+    audio: Res<Audio>,
+    audio_channels: Res<AudioChannels>,
+) {
+    commands.insert_resource(PauseMenu::default());
+
+    audio.pause_channel(&audio_channels.background);
+    audio.pause_channel(&audio_channels.fx);
+    audio.pause_channel(&audio_channels.fx_ufo);
+    audio.pause_channel(&audio_channels.engine);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: backdrop.0.clone(),
+            ..Default::default()
+        })
+        .insert(PauseOverlay)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "Paused\n".into(),
+                                style: TextStyle {
+                                    font: font.0.clone(),
+                                    font_size: 90.,
+                                    color: Color::ORANGE_RED,
+                                },
+                            },
+                            TextSection {
+                                value: String::new(),
+                                style: TextStyle {
+                                    font: font.0.clone(),
+                                    font_size: 42.,
+                                    color: Color::WHITE,
+                                },
+                            },
+                        ],
+                        alignment: Default::default(),
+                    },
+                    ..Default::default()
+                })
+                .insert(PauseOverlay);
+        });
+}
+
+fn navigate(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    mut menu: ResMut<PauseMenu>,
+    mut volumes: ResMut<Volumes>,
+    mut leaving: ResMut<Leaving>,
+    mut q_text: Query<&mut Text, With<PauseOverlay>>,
+) {
+    let count = if menu.settings {
+        Setting::ALL.len()
+    } else {
+        Entry::ALL.len()
+    };
+    if keyboard.just_pressed(KeyCode::Up) {
+        menu.selected = (menu.selected + count - 1) % count;
+    } else if keyboard.just_pressed(KeyCode::Down) {
+        menu.selected = (menu.selected + 1) % count;
+    }
+    let confirm = keyboard.just_pressed(KeyCode::Return) || keyboard.just_pressed(KeyCode::Space);
+    let back = keyboard.just_pressed(KeyCode::Escape) || keyboard.just_pressed(KeyCode::P);
+
+    if menu.settings {
+        let step = if keyboard.just_pressed(KeyCode::Left) {
+            -VOLUME_STEP
+        } else if keyboard.just_pressed(KeyCode::Right) {
+            VOLUME_STEP
+        } else {
+            0.0
+        };
+        match Setting::ALL[menu.selected] {
+            Setting::Music if step != 0.0 => {
+                volumes.music = (volumes.music + step).clamp(0.0, 1.0);
+            }
+            Setting::Effects if step != 0.0 => {
+                volumes.effects = (volumes.effects + step).clamp(0.0, 1.0);
+            }
+            Setting::Back if confirm => *menu = PauseMenu::default(),
+            _ if back => *menu = PauseMenu::default(),
+            _ => {}
+        }
+    } else if back {
+        state.pop().unwrap();
+    } else if confirm {
+        match Entry::ALL[menu.selected] {
+            Entry::Resume => {}
+            Entry::Restart => leaving.0 = Some(Leave::Restart),
+            Entry::Settings => {
+                menu.settings = true;
+                menu.selected = 0;
+            }
+            Entry::Quit => leaving.0 = Some(Leave::Quit),
+        }
+        if !menu.settings {
+            state.pop().unwrap();
+        }
+    }
+    // The game must not see the keys closing the menu
+    for key in [KeyCode::Return, KeyCode::Space, KeyCode::Escape, KeyCode::P] {
+        keyboard.reset(key);
+    }
+
+    let lines = if menu.settings {
+        Setting::ALL
+            .iter()
+            .map(|setting| setting.describe(&volumes))
+            .collect::<Vec<_>>()
+    } else {
+        Entry::ALL
+            .iter()
+            .map(|entry| entry.name().to_string())
+            .collect()
+    };
+    let menu_text = lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let cursor = if index == menu.selected { ">" } else { " " };
+            format!("{} {}\n", cursor, line)
+        })
+        .collect::<String>();
+    for mut text in q_text.iter_mut() {
+        if let Some(section) = text.sections.get_mut(1) {
+            section.value = menu_text.clone();
+        }
+    }
+}
+
+fn remove_overlay(
+    mut commands: Commands,
+    audio: Res<Audio>,
+    audio_channels: Res<AudioChannels>,
+    query: Query<Entity, With<PauseOverlay>>,
+) {
+    commands.remove_resource::<PauseMenu>();
+
+    audio.resume_channel(&audio_channels.background);
+    audio.resume_channel(&audio_channels.fx);
+    audio.resume_channel(&audio_channels.fx_ufo);
+    audio.resume_channel(&audio_channels.engine);
+
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+/// Restarting goes back to the ship selection, which replaces the game
+fn leave(mut leaving: ResMut<Leaving>, mut state: ResMut<State<AppState>>) {
+    match leaving.0.take() {
+        Some(Leave::Restart) => state.set(AppState::ShipSelect).unwrap(),
+        Some(Leave::Quit) => state.pop().unwrap(),
+        None => {}
+    }
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Leaving>()
+            .add_startup_system(prepare_resources.system())
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(pause.system()))
+            .add_system_set(SystemSet::on_resume(AppState::Game).with_system(leave.system()))
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(add_overlay.system()))
+            .add_system_set(SystemSet::on_update(AppState::Paused).with_system(navigate.system()))
+            .add_system_set(
+                SystemSet::on_exit(AppState::Paused).with_system(remove_overlay.system()),
+            );
+    }
+}
@@ -1,94 +1,208 @@
 use crate::{
-    Acceleration, AppState, Collider2D, CollisionEvent, CollisionLayer, CollisionMask,
-    ControlLocked, Fire, Friction, PlayerControlled, PlayerLifes, Shape2D, Thrust, Velocity, Wrap,
-    AMMO, OBSTACLE, PLAYER,
+    wrapped_offset, Acceleration, AppState, CameraEffect, Collider2D, CollisionEvent,
+    CollisionLayer, CollisionMask, ControlLocked, Damage, Friction, GameClock, GameFont, GameMode,
+    Ghost, Health, Invulnerable, ParticleEmitter, PlayerControlled, PlayerLifes, Pool,
+    SelectedShip, Shape2D, Shielded, ShipClasses, Thruster, Velocity, Wrap, WrapCamera, AMMO,
+    ENEMY_AMMO, OBSTACLE, PLAYER,
 };
-use rand::prelude::*;
 use std::collections::HashSet;
 
 use bevy::{
-    app::{AppBuilder, EventReader, Plugin},
+    app::{AppBuilder, EventReader, EventWriter, Plugin},
     asset::{AssetServer, Assets, Handle},
     core::{Time, Timer},
     ecs::{
         entity::Entity,
-        query::{Added, Or, With},
+        query::{Added, Or, With, Without},
         schedule::SystemSet,
         system::{Commands, IntoSystem, Query, Res, ResMut},
     },
-    math::{Vec2, Vec3},
-    render::color::Color,
-    sprite::{
-        entity::{SpriteBundle, SpriteSheetBundle},
-        ColorMaterial, Sprite, TextureAtlas, TextureAtlasSprite,
+    math::{Rect, Vec2, Vec3},
+    render::{camera::OrthographicProjection, color::Color, draw::Visible},
+    sprite::{entity::SpriteSheetBundle, TextureAtlas, TextureAtlasSprite},
+    text::{Text, TextSection, TextStyle},
+    transform::{
+        components::{Parent, Transform},
+        hierarchy::{BuildChildren, DespawnRecursiveExt},
     },
-    transform::components::Transform,
+    ui::{entity::TextBundle, AlignSelf, PositionType, Style, Val},
 };
 
 struct Player;
+
+/// Emitted when the ship is damaged or destroyed
+pub struct PlayerHit {
+    pub ship: Entity,
+}
 struct SpawnPlayer(Timer);
+struct RespawnIndicator;
+struct HullDamageOverlay;
 pub struct PlayerTexture(pub Handle<TextureAtlas>);
+struct HullDamageTexture(Handle<TextureAtlas>);
+/// Frames of the ship classes, a row per class
+struct ShipClassTexture(Handle<TextureAtlas>);
+
+/// How the ship reacts to hits.
+/// In `Classic` mode, the ship explodes on first hit.
+/// In `Hull` mode, the ship is given a `Health` and each hit removes the
+/// `Damage` of what it collided with. After a hit, the ship is invulnerable
+/// for `invulnerability` seconds.
+#[derive(Debug, Clone, Copy)]
+pub enum DamageModel {
+    Classic,
+    Hull { health: u16, invulnerability: f32 },
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for DamageModel {
+    fn default() -> Self {
+        DamageModel::Classic
+    }
+}
+
+impl DamageModel {
+    /// Hull model offered on the ship selection screen
+    pub const HULL: DamageModel = DamageModel::Hull {
+        health: 6,
+        invulnerability: 1.5,
+    };
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DamageModel::Classic => "Classic",
+            DamageModel::Hull { .. } => "Hull",
+        }
+    }
+
+    /// Switches between the one-hit model and the hull one
+    pub fn toggled(self) -> Self {
+        match self {
+            DamageModel::Classic => DamageModel::HULL,
+            DamageModel::Hull { .. } => DamageModel::Classic,
+        }
+    }
+}
+
+/// Respawn configuration.
+/// Once the respawn delay is over, the ship only appears if no obstacle lies
+/// within `clear_radius` of its spawn point. Otherwise, it either waits for
+/// the area to clear or, if `relocate` is set, picks the nearest safe spot.
+pub struct SafeRespawn {
+    pub clear_radius: f32,
+    pub relocate: bool,
+}
+
+impl Default for SafeRespawn {
+    fn default() -> Self {
+        SafeRespawn {
+            clear_radius: 150.0,
+            relocate: true,
+        }
+    }
+}
 struct Immunity {
     duration: Timer,
     animation: Timer,
 }
 
+/// Row of its texture atlas used by a ship
+struct ShipRow(u32);
+
+impl ShipRow {
+    fn frame(&self, frame: u32) -> u32 {
+        self.0 * SHIP_FRAMES + frame
+    }
+}
+
+/// Layers the ship collides with when it is vulnerable
+pub const PLAYER_MASK: u8 = OBSTACLE | ENEMY_AMMO;
+
+const SHIP_FRAMES: u32 = 13;
+const SHIP_CLASS_ROWS: usize = 3;
 const SPRITE_FULL_SHIELD: u32 = 11;
 const SPRITE_NO_SHIELD: u32 = 12;
-struct PlayerColors(Vec<Handle<ColorMaterial>>);
+const HULL_DAMAGE_FRAMES: u32 = 3;
+struct PlayerColors(Vec<Color>);
 
 fn destroy_on_collision(
     mut commands: Commands,
     mut events: EventReader<CollisionEvent>,
     mut lifes: ResMut<PlayerLifes>,
     colors: Res<PlayerColors>,
-    q_player: Query<(Entity, &Velocity, &Transform), With<Player>>,
+    mut emitters: ResMut<Pool<ParticleEmitter>>,
+    mut effects: EventWriter<CameraEffect>,
+    mut hits: EventWriter<PlayerHit>,
+    mode: Res<GameMode>,
+    clock: Res<GameClock>,
+    damage_model: Res<DamageModel>,
+    mut q_player: Query<
+        (Entity, &Velocity, &Transform, Option<&mut Health>),
+        (With<Player>, Without<Invulnerable>, Without<Shielded>),
+    >,
+    q_damage: Query<&Damage>,
+    q_ghosts: Query<&Ghost>,
 ) {
-    let mut rng = thread_rng();
+    if !mode.mortal() {
+        return;
+    }
+
     let mut already_done = HashSet::new();
     for collision in events.iter() {
-        if let Ok((e, ship_velocity, ship_transform)) = q_player.get(collision.source) {
+        if let Ok((e, ship_velocity, ship_transform, health)) = q_player.get_mut(collision.source) {
             if already_done.contains(&e) {
                 continue;
             }
             already_done.insert(e);
+            hits.send(PlayerHit { ship: e });
 
-            commands.entity(e).despawn();
-            commands.spawn().insert(SpawnPlayer::default());
-
-            // Create particles
-            for _ in 0..500 as u16 {
-                let size = {
-                    let size = rng.gen_range(1.0..3.0);
-                    Vec2::new(size, size)
+            if let (
+                Some(mut health),
+                DamageModel::Hull {
+                    invulnerability, ..
+                },
+            ) = (health, *damage_model)
+            {
+                let target = if let Ok(ghost) = q_ghosts.get(collision.target) {
+                    ghost.target
+                } else {
+                    collision.target
+                };
+                let damage = if let Ok(damage) = q_damage.get(target) {
+                    damage.0
+                } else {
+                    1
                 };
 
-                let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
-                let far = rng.gen_range(0.0..32.0);
-
-                let relative_position = Vec3::new(angle.cos() * far, angle.sin() * far, 0.0);
+                if !health.damage(damage) {
+                    commands.entity(e).insert(Invulnerable::new(
+                        invulnerability,
+                        CollisionMask(PLAYER_MASK),
+                    ));
+                    continue;
+                }
+            }
 
-                let velocity = ship_velocity.translation + (relative_position * 50.0).into();
-                commands
-                    .spawn_bundle(SpriteBundle {
-                        material: colors.0[rng.gen_range(0..colors.0.len())].clone(),
-                        transform: Transform::from_translation(
-                            ship_transform.translation + relative_position,
-                        ),
-                        sprite: Sprite::new(size),
-                        ..Default::default()
-                    })
-                    .insert(Velocity::new(velocity, 0.0))
-                    .insert(Collider2D {
-                        shape: Shape2D::Rectangle(size),
-                        ..Default::default()
-                    })
-                    .insert(Fire)
-                    .insert(CollisionLayer(AMMO))
-                    .insert(CollisionMask(OBSTACLE));
+            commands.entity(e).despawn_recursive();
+            lifes.0 = lifes.0.saturating_sub(1);
+            if !mode.is_over(lifes.0, clock.elapsed) {
+                commands.spawn().insert(SpawnPlayer::default());
             }
 
-            lifes.0 -= 1;
+            let emitter = emitters.acquire(&mut commands);
+            commands
+                .entity(emitter)
+                .insert(ParticleEmitter::blast(
+                    colors.0.clone(),
+                    ship_velocity.translation,
+                    AMMO,
+                ))
+                .insert(Transform::from_translation(ship_transform.translation));
+
+            effects.send(CameraEffect::Shake(0.9));
+            effects.send(CameraEffect::HitStop {
+                scale: 0.1,
+                duration: 0.3,
+            });
         }
     }
 }
@@ -115,33 +229,52 @@ impl Default for Immunity {
 fn remove_immunity(
     mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(Entity, &mut TextureAtlasSprite, &mut Immunity), With<Player>>,
+    mut query: Query<(Entity, &mut TextureAtlasSprite, &ShipRow, &mut Immunity), With<Player>>,
 ) {
-    for (id, mut sprite, mut immunity) in query.iter_mut() {
+    for (id, mut sprite, row, mut immunity) in query.iter_mut() {
         if immunity.animation.tick(time.delta()).just_finished() {
-            if sprite.index < SPRITE_FULL_SHIELD {
+            if sprite.index < row.frame(SPRITE_FULL_SHIELD) {
                 sprite.index += 1;
             }
         }
 
         if immunity.duration.tick(time.delta()).just_finished() {
-            sprite.index = SPRITE_NO_SHIELD;
+            sprite.index = row.frame(SPRITE_NO_SHIELD);
             commands
                 .entity(id)
                 .remove::<Immunity>()
                 .remove::<ControlLocked>()
-                .insert(CollisionMask(OBSTACLE));
+                .insert(CollisionMask(PLAYER_MASK));
+        }
+    }
+}
+
+/// Shielded ships show their full shield, once the spawn immunity is over
+fn display_shield(
+    mut query: Query<
+        (&mut TextureAtlasSprite, &ShipRow, Option<&Shielded>),
+        (With<Player>, Without<Immunity>),
+    >,
+) {
+    for (mut sprite, row, shielded) in query.iter_mut() {
+        let index = if shielded.is_some() {
+            row.frame(SPRITE_FULL_SHIELD)
+        } else {
+            row.frame(SPRITE_NO_SHIELD)
+        };
+        if sprite.index != index {
+            sprite.index = index;
         }
     }
 }
 
 fn new_immunity(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut TextureAtlasSprite), (With<Player>, Added<Immunity>)>,
+    mut query: Query<(Entity, &mut TextureAtlasSprite, &ShipRow), (With<Player>, Added<Immunity>)>,
 ) {
-    for (e, mut sprite) in query.iter_mut() {
-        if sprite.index > SPRITE_FULL_SHIELD {
-            sprite.index = SPRITE_FULL_SHIELD;
+    for (e, mut sprite, row) in query.iter_mut() {
+        if sprite.index > row.frame(SPRITE_FULL_SHIELD) {
+            sprite.index = row.frame(SPRITE_FULL_SHIELD);
         }
 
         commands
@@ -151,28 +284,145 @@ fn new_immunity(
     }
 }
 
+/// Looks for the closest position to the center of the screen where no
+/// obstacle lies within the clear radius.
+/// Candidates are tested on rings growing around the center, distances being
+/// computed across the screen edges.
+fn find_spawn_position(
+    respawn: &SafeRespawn,
+    projection: &OrthographicProjection,
+    obstacles: &[(Vec2, f32)],
+) -> Option<Vec2> {
+    let is_clear = |position: Vec2| {
+        obstacles.iter().all(|(obstacle, radius)| {
+            wrapped_offset(position, *obstacle, projection).length() > respawn.clear_radius + radius
+        })
+    };
+
+    let center = Vec2::new(
+        (projection.left + projection.right) / 2.0,
+        (projection.bottom + projection.top) / 2.0,
+    );
+
+    if is_clear(center) {
+        return Some(center);
+    }
+
+    if !respawn.relocate {
+        return None;
+    }
+
+    let step = (respawn.clear_radius / 2.0).max(16.0);
+    let max_distance = f32::min(
+        projection.right - projection.left,
+        projection.top - projection.bottom,
+    ) / 2.0;
+
+    let mut distance = step;
+    while distance < max_distance {
+        let count = (std::f32::consts::PI * 2.0 * distance / step).ceil() as u32;
+        for i in 0..count {
+            let angle = i as f32 * std::f32::consts::PI * 2.0 / count as f32;
+            let position = center + Vec2::new(angle.cos(), angle.sin()) * distance;
+            if is_clear(position) {
+                return Some(position);
+            }
+        }
+        distance += step;
+    }
+
+    None
+}
+
 fn spawn_player(
     mut commands: Commands,
     time: Res<Time>,
     texture_atlas: Res<PlayerTexture>,
+    class_texture: Res<ShipClassTexture>,
+    damage_texture: Res<HullDamageTexture>,
+    damage_model: Res<DamageModel>,
+    respawn: Res<SafeRespawn>,
+    classes: Res<ShipClasses>,
+    selected: Res<SelectedShip>,
+    mode: Res<GameMode>,
+    clock: Res<GameClock>,
+    lifes: Res<PlayerLifes>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_obstacles: Query<(&Transform, &Collider2D, &CollisionLayer), Without<Ghost>>,
     mut q_spawn: Query<(Entity, &mut SpawnPlayer)>,
 ) {
     for (entity, mut spawn) in q_spawn.iter_mut() {
-        if spawn.0.tick(time.delta()).just_finished() {
+        // No new ship once the game is ending
+        if mode.is_over(lifes.0, clock.elapsed) {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if !spawn.0.tick(time.delta()).finished() {
+            continue;
+        }
+
+        let position = if let Ok(projection) = q_projection.single() {
+            let obstacles: Vec<(Vec2, f32)> = q_obstacles
+                .iter()
+                .filter(|(_, _, layer)| layer.0 & OBSTACLE > 0)
+                .map(|(transform, collider, _)| {
+                    (
+                        transform.translation.truncate() + collider.position,
+                        collider.shape.bounding_radius(),
+                    )
+                })
+                .collect();
+
+            find_spawn_position(&respawn, projection, &obstacles)
+        } else {
+            Some(Vec2::ZERO)
+        };
+
+        if let Some(position) = position {
+            let class = selected.class(&classes);
+            let (texture_atlas, row) = match class.sprite_row {
+                Some(row) => (class_texture.0.clone(), ShipRow(row)),
+                None => (texture_atlas.0.clone(), ShipRow(0)),
+            };
+
+            if let DamageModel::Hull { health, .. } = *damage_model {
+                commands
+                    .entity(entity)
+                    .insert(Health::new(health))
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(SpriteSheetBundle {
+                                texture_atlas: damage_texture.0.clone(),
+                                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.1)),
+                                ..Default::default()
+                            })
+                            .insert(HullDamageOverlay);
+                    });
+            }
+
             commands
                 .entity(entity)
                 .remove::<SpawnPlayer>()
                 .insert_bundle(SpriteSheetBundle {
-                    texture_atlas: texture_atlas.0.clone(),
+                    texture_atlas,
+                    transform: Transform::from_translation(position.extend(0.0)),
+                    sprite: TextureAtlasSprite {
+                        index: row.frame(0),
+                        color: class.color.unwrap_or(Color::WHITE),
+                        ..Default::default()
+                    },
                     ..Default::default()
                 })
                 .insert(Velocity::default())
                 .insert(Acceleration::default())
-                .insert(Thrust::default())
-                .insert(Friction(1.0))
+                .insert(class.thrust)
+                .insert(Friction(class.friction))
+                .insert(class.weapon)
+                .insert(row)
+                .insert(Thruster::default())
                 .insert(PlayerControlled)
                 .insert(Collider2D {
-                    shape: Shape2D::Circle(32.0),
+                    shape: Shape2D::Circle(class.collider_radius),
                     ..Default::default()
                 })
                 .insert(CollisionLayer(PLAYER))
@@ -184,36 +434,151 @@ fn spawn_player(
     }
 }
 
+/// Gives the ghosts of a damageable ship their own hull damage overlay
+fn add_ghost_hull_damage(
+    mut commands: Commands,
+    damage_texture: Res<HullDamageTexture>,
+    q_player: Query<(), (With<Player>, With<Health>)>,
+    q_ghosts: Query<(Entity, &Ghost), Added<Ghost>>,
+) {
+    for (e, ghost) in q_ghosts.iter() {
+        if q_player.get(ghost.target).is_ok() {
+            commands.entity(e).with_children(|parent| {
+                parent
+                    .spawn_bundle(SpriteSheetBundle {
+                        texture_atlas: damage_texture.0.clone(),
+                        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.1)),
+                        ..Default::default()
+                    })
+                    .insert(HullDamageOverlay);
+            });
+        }
+    }
+}
+
+/// Picks the hull damage overlay frame matching the ship health, on the ship
+/// and its ghosts. Overlays blink along with the ship.
+fn display_hull_damage(
+    q_player: Query<(&Health, &Visible), (With<Player>, Without<HullDamageOverlay>)>,
+    q_ghosts: Query<&Ghost>,
+    mut q_overlay: Query<(&Parent, &mut TextureAtlasSprite, &mut Visible), With<HullDamageOverlay>>,
+) {
+    for (parent, mut sprite, mut visible) in q_overlay.iter_mut() {
+        let ship = q_ghosts
+            .get(parent.0)
+            .map_or(parent.0, |ghost| ghost.target);
+        if let Ok((health, ship_visible)) = q_player.get(ship) {
+            let frame =
+                ((health.wear() * HULL_DAMAGE_FRAMES as f32).ceil() as u32).min(HULL_DAMAGE_FRAMES);
+            if sprite.index != frame {
+                sprite.index = frame;
+            }
+            if visible.is_visible != ship_visible.is_visible {
+                visible.is_visible = ship_visible.is_visible;
+            }
+        }
+    }
+}
+
+/// Shows the respawn indicator as long as a ship is waiting to spawn
+fn display_respawn_indicator(
+    q_spawn: Query<&SpawnPlayer>,
+    mut q_indicator: Query<(&mut Text, &mut Visible), With<RespawnIndicator>>,
+) {
+    let pending = q_spawn.iter().next();
+    for (mut text, mut visible) in q_indicator.iter_mut() {
+        visible.is_visible = pending.is_some();
+        if let Some(spawn) = pending {
+            text.sections[0].value = if spawn.0.finished() {
+                "Waiting for a clear space".into()
+            } else {
+                "Get ready".into()
+            };
+        }
+    }
+}
+
 pub fn prepare_resources(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     commands.insert_resource(PlayerTexture(texture_atlases.add(TextureAtlas::from_grid(
         asset_server.load("sprites/ship.png"),
         Vec2::new(64.0, 64.0),
-        13,
+        SHIP_FRAMES as usize,
         1,
     ))));
 
+    commands.insert_resource(ShipClassTexture(texture_atlases.add(
+        TextureAtlas::from_grid(
+            asset_server.load("sprites/ship_classes.png"),
+            Vec2::new(64.0, 64.0),
+            SHIP_FRAMES as usize,
+            SHIP_CLASS_ROWS,
+        ),
+    )));
+
+    commands.insert_resource(HullDamageTexture(texture_atlases.add(
+        TextureAtlas::from_grid(
+            asset_server.load("sprites/hull_damage.png"),
+            Vec2::new(64.0, 64.0),
+            HULL_DAMAGE_FRAMES as usize + 1,
+            1,
+        ),
+    )));
+
     commands.insert_resource(PlayerColors(vec![
-        materials.add(Color::rgb(0.36, 0.43, 1.00).into()),
-        materials.add(Color::rgb(0.37, 0.80, 0.89).into()),
-        materials.add(Color::rgb(0.67, 0.20, 0.20).into()),
-        materials.add(Color::rgb(0.27, 0.16, 0.16).into()),
-        materials.add(Color::rgb(0.86, 0.90, 0.99).into()),
-        materials.add(Color::rgb(0.47, 0.53, 0.55).into()),
+        Color::rgb(0.36, 0.43, 1.00),
+        Color::rgb(0.37, 0.80, 0.89),
+        Color::rgb(0.67, 0.20, 0.20),
+        Color::rgb(0.27, 0.16, 0.16),
+        Color::rgb(0.86, 0.90, 0.99),
+        Color::rgb(0.47, 0.53, 0.55),
     ]));
 }
 
-fn enter(mut commands: Commands) {
+fn enter(mut commands: Commands, font: Res<GameFont>) {
     commands.spawn().insert(SpawnPlayer::default());
+
+    commands
+        .spawn_bundle(TextBundle {
+            visible: Visible {
+                is_visible: false,
+                ..Default::default()
+            },
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(40.),
+                    bottom: Val::Percent(60.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![TextSection {
+                    value: "Get ready".into(),
+                    style: TextStyle {
+                        font: font.0.clone(),
+                        font_size: 42.,
+                        color: Color::BLUE,
+                    },
+                }],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        })
+        .insert(RespawnIndicator);
 }
 
-fn exit(mut commands: Commands, query: Query<Entity, Or<(With<Player>, With<SpawnPlayer>)>>) {
+fn exit(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<Player>, With<SpawnPlayer>, With<RespawnIndicator>)>>,
+) {
     for e in query.iter() {
-        commands.entity(e).despawn();
+        commands.entity(e).despawn_recursive();
     }
 }
 
@@ -221,10 +586,17 @@ pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(prepare_resources.system())
+        app.add_event::<PlayerHit>()
+            .init_resource::<SafeRespawn>()
+            .init_resource::<DamageModel>()
+            .add_startup_system(prepare_resources.system())
             .add_system_set(
                 SystemSet::on_update(AppState::Game)
                     .with_system(spawn_player.system())
+                    .with_system(display_respawn_indicator.system())
+                    .with_system(add_ghost_hull_damage.system())
+                    .with_system(display_hull_damage.system())
+                    .with_system(display_shield.system())
                     .with_system(remove_immunity.system())
                     .with_system(new_immunity.system())
                     .with_system(destroy_on_collision.system()),
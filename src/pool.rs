@@ -0,0 +1,323 @@
+/// Entity pools
+/// Entities coming and going by the hundreds, like projectiles, particle
+/// emitters and asteroids, are not despawned but released to their `Pool`.
+/// A released entity is hidden, loses its gameplay components as defined by
+/// its `Poolable` kind, and is marked `Pooled` until `acquire` hands it over
+/// again.
+///
+/// Pools save the entity allocations and let the rendering components, with
+/// their mesh and material handles, be reused. They do not save archetype
+/// moves: the gameplay components are removed on release and inserted again
+/// on acquisition, so that systems need no `Without<Pooled>` filter.
+///
+/// Acquired entities carry a `Recycle` component, so that systems unaware of
+/// pools, like the wrapping despawners, release them instead of despawning
+/// them.
+///
+/// Each pool reports its size and its misses, acquisitions which had to spawn
+/// a new entity, as diagnostics.
+use bevy::{
+    app::{AppBuilder, Plugin},
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        system::{Command, Commands, IntoSystem, Res, ResMut},
+        world::{EntityMut, World},
+    },
+    render::draw::Visible,
+};
+use std::marker::PhantomData;
+
+const DIAGNOSTIC_HISTORY: usize = 20;
+
+/// Kind of entities sharing a pool, named after the component they all have
+/// while in use
+pub trait Poolable: Component {
+    const NAME: &'static str;
+    const SIZE: DiagnosticId;
+    const MISSES: DiagnosticId;
+
+    /// Removes the components given to the entity while in use. Any
+    /// component the spawning code inserts and which is not listed here stays
+    /// on the idle entity, where every system still sees it.
+    fn reset(entity: &mut EntityMut);
+}
+
+/// Marks entities waiting in a pool
+pub struct Pooled;
+
+/// Given to acquired entities, tells how to release them
+#[derive(Clone, Copy)]
+pub struct Recycle(fn(&mut Commands, Entity));
+
+impl Recycle {
+    pub fn release(&self, commands: &mut Commands, entity: Entity) {
+        (self.0)(commands, entity)
+    }
+}
+
+/// Releases the entity if it came from a pool, despawns it otherwise
+pub fn recycle_or_despawn(commands: &mut Commands, entity: Entity, recycle: Option<&Recycle>) {
+    match recycle {
+        Some(recycle) => recycle.release(commands, entity),
+        None => commands.entity(entity).despawn(),
+    }
+}
+
+pub struct Pool<T> {
+    free: Vec<Entity>,
+    /// Entities spawned by the pool and not despawned by it
+    size: usize,
+    misses: usize,
+    /// Released entities beyond this number of idle ones are despawned
+    pub max_idle: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        Pool {
+            free: Vec::new(),
+            size: 0,
+            misses: 0,
+            max_idle: 512,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Poolable> Pool<T> {
+    /// Idle entity of the pool, or a new one if there is none. Either way the
+    /// caller inserts the components it needs.
+    pub fn acquire(&mut self, commands: &mut Commands) -> Entity {
+        let recycle = Recycle(release::<T>);
+        match self.free.pop() {
+            Some(entity) => {
+                commands.entity(entity).remove::<Pooled>().insert(recycle);
+                entity
+            }
+            None => {
+                self.size += 1;
+                self.misses += 1;
+                commands.spawn().insert(recycle).id()
+            }
+        }
+    }
+
+    /// Hands the entity back to the pool at the end of the stage. Entities
+    /// already released or despawned by then are ignored.
+    pub fn release(&self, commands: &mut Commands, entity: Entity) {
+        release::<T>(commands, entity);
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Entities waiting to be acquired
+    pub fn idle(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+fn release<T: Poolable>(commands: &mut Commands, entity: Entity) {
+    commands.add(Release::<T> {
+        entity,
+        marker: PhantomData,
+    });
+}
+
+struct Release<T> {
+    entity: Entity,
+    marker: PhantomData<T>,
+}
+
+impl<T: Poolable> Command for Release<T> {
+    fn write(self: Box<Self>, world: &mut World) {
+        let (idle, max_idle) = match world.get_resource::<Pool<T>>() {
+            Some(pool) => (pool.free.len(), pool.max_idle),
+            None => return,
+        };
+        match world.get_entity(self.entity) {
+            Some(entity) if !entity.contains::<Pooled>() => {}
+            _ => return,
+        }
+
+        if idle >= max_idle {
+            world.despawn(self.entity);
+            if let Some(mut pool) = world.get_resource_mut::<Pool<T>>() {
+                pool.size = pool.size.saturating_sub(1);
+            }
+            return;
+        }
+
+        if let Some(mut entity) = world.get_entity_mut(self.entity) {
+            T::reset(&mut entity);
+            entity.remove::<Recycle>();
+            if let Some(mut visible) = entity.get_mut::<Visible>() {
+                visible.is_visible = false;
+            }
+            entity.insert(Pooled);
+        }
+        if let Some(mut pool) = world.get_resource_mut::<Pool<T>>() {
+            pool.free.push(self.entity);
+        }
+    }
+}
+
+fn setup_diagnostics<T: Poolable>(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(
+        T::SIZE,
+        format!("{}_pool_size", T::NAME),
+        DIAGNOSTIC_HISTORY,
+    ));
+    diagnostics.add(Diagnostic::new(
+        T::MISSES,
+        format!("{}_pool_misses", T::NAME),
+        DIAGNOSTIC_HISTORY,
+    ));
+}
+
+fn measure<T: Poolable>(pool: Res<Pool<T>>, mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add_measurement(T::SIZE, pool.size as f64);
+    diagnostics.add_measurement(T::MISSES, pool.misses as f64);
+}
+
+/// Pool of one kind of entities, with its diagnostics
+pub struct PoolPlugin<T>(PhantomData<T>);
+
+impl<T> Default for PoolPlugin<T> {
+    fn default() -> Self {
+        PoolPlugin(PhantomData)
+    }
+}
+
+impl<T: Poolable> Plugin for PoolPlugin<T> {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Pool<T>>()
+            .add_startup_system(setup_diagnostics::<T>.system())
+            .add_system(measure::<T>.system());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::CommandQueue;
+
+    struct Rock;
+
+    impl Poolable for Rock {
+        const NAME: &'static str = "rock";
+        const SIZE: DiagnosticId = DiagnosticId::from_u128(0x1);
+        const MISSES: DiagnosticId = DiagnosticId::from_u128(0x2);
+
+        fn reset(entity: &mut EntityMut) {
+            entity.remove::<Rock>();
+        }
+    }
+
+    fn with_pool<R>(world: &mut World, f: impl FnOnce(&mut Pool<Rock>, &mut Commands) -> R) -> R {
+        let mut pool = world.remove_resource::<Pool<Rock>>().unwrap();
+        let mut queue = CommandQueue::default();
+        let result = f(&mut pool, &mut Commands::new(&mut queue, world));
+        world.insert_resource(pool);
+        queue.apply(world);
+        result
+    }
+
+    fn acquire(world: &mut World) -> Entity {
+        with_pool(world, |pool, commands| {
+            let entity = pool.acquire(commands);
+            commands
+                .entity(entity)
+                .insert(Rock)
+                .insert(Visible::default());
+            entity
+        })
+    }
+
+    fn release(world: &mut World, entity: Entity) {
+        with_pool(world, |pool, commands| pool.release(commands, entity));
+    }
+
+    fn pool(world: &World) -> &Pool<Rock> {
+        world.get_resource::<Pool<Rock>>().unwrap()
+    }
+
+    #[test]
+    fn released_entities_are_reset_and_reused() {
+        let mut world = World::default();
+        world.insert_resource(Pool::<Rock>::default());
+
+        let rock = acquire(&mut world);
+        assert!(world.get::<Recycle>(rock).is_some());
+        release(&mut world, rock);
+        assert!(world.get::<Rock>(rock).is_none());
+        assert!(world.get::<Recycle>(rock).is_none());
+        assert!(world.get::<Pooled>(rock).is_some());
+        assert!(!world.get::<Visible>(rock).unwrap().is_visible);
+        assert_eq!(pool(&world).idle(), 1);
+
+        assert_eq!(acquire(&mut world), rock);
+        assert!(world.get::<Pooled>(rock).is_none());
+        assert_eq!(pool(&world).idle(), 0);
+        assert_eq!(pool(&world).size(), 1);
+    }
+
+    #[test]
+    fn releasing_twice_or_after_despawn_is_ignored() {
+        let mut world = World::default();
+        world.insert_resource(Pool::<Rock>::default());
+
+        let rock = acquire(&mut world);
+        release(&mut world, rock);
+        release(&mut world, rock);
+        assert_eq!(pool(&world).idle(), 1);
+
+        let other = acquire(&mut world);
+        let despawned = acquire(&mut world);
+        world.despawn(despawned);
+        release(&mut world, despawned);
+        assert_eq!(pool(&world).idle(), 0);
+        assert!(world.get_entity(other).is_some());
+    }
+
+    #[test]
+    fn entities_beyond_max_idle_are_despawned() {
+        let mut world = World::default();
+        world.insert_resource(Pool::<Rock> {
+            max_idle: 1,
+            ..Default::default()
+        });
+
+        let first = acquire(&mut world);
+        let second = acquire(&mut world);
+        assert_eq!(pool(&world).size(), 2);
+        release(&mut world, first);
+        release(&mut world, second);
+        assert!(world.get_entity(first).is_some());
+        assert!(world.get_entity(second).is_none());
+        assert_eq!(pool(&world).idle(), 1);
+        assert_eq!(pool(&world).size(), 1);
+    }
+
+    #[test]
+    fn misses_count_the_spawned_entities() {
+        let mut world = World::default();
+        world.insert_resource(Pool::<Rock>::default());
+
+        let rock = acquire(&mut world);
+        assert_eq!(pool(&world).misses(), 1);
+        release(&mut world, rock);
+        acquire(&mut world);
+        assert_eq!(pool(&world).misses(), 1);
+        acquire(&mut world);
+        assert_eq!(pool(&world).misses(), 2);
+    }
+}
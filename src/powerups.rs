@@ -0,0 +1,605 @@
+/// Power-ups
+/// Destroyed asteroids sometimes drop a `Pickup`, according to the
+/// `DropTable`. Pickups drift and wrap for a while on the `PICKUP` layer, and
+/// are collected by flying the ship through them.
+///
+/// Timed power-ups become `Buffs`, displayed as icons with a gauge in the
+/// top right corner of the screen. They all end when the ship is destroyed.
+///
+/// The shield makes the ship `Shielded`: hits do not damage it, and it shows
+/// its full shield frame instead of blinking like after a hit.
+///
+/// While the score magnet is active, destroyed asteroids scatter
+/// `ScoreShard`s worth extra points, which are pulled towards the ship along
+/// with the pickups.
+use crate::{
+    wrapped_offset, AppState, AsteroidClass, AsteroidDestroyed, AudioChannels, BonusLifes,
+    Collider2D, CollisionEvent, CollisionLayer, CollisionMask, Ghost, Health, LifeGained,
+    NoWrapProtection, PlayerControlled, PlayerLifes, Score, Shape2D, SoundEffects, TimeScale,
+    Velocity, Weapon, Wrap, WrapCamera, PICKUP, PLAYER,
+};
+use bevy::{
+    app::{AppBuilder, EventReader, EventWriter, Plugin},
+    asset::{AssetServer, Assets, Handle},
+    core::{Time, Timer},
+    ecs::{
+        entity::Entity,
+        query::{Or, With},
+        schedule::SystemSet,
+        system::{Commands, IntoSystem, Query, RemovedComponents, Res, ResMut},
+    },
+    math::{Vec2, Vec3},
+    render::{camera::OrthographicProjection, color::Color},
+    sprite::{
+        entity::{SpriteBundle, SpriteSheetBundle},
+        ColorMaterial, Sprite, TextureAtlas, TextureAtlasSprite,
+    },
+    transform::components::Transform,
+};
+use bevy_kira_audio::Audio;
+use rand::prelude::*;
+use std::{collections::HashSet, time::Duration};
+
+const PICKUP_LIFETIME: u64 = 12;
+const PICKUP_SPEED: f32 = 60.0;
+const MAGNET_PULL: f32 = 600.0;
+const SLOW_TIME_SCALE: f32 = 0.4;
+const SCORE_SHARDS: usize = 3;
+const SCORE_SHARD_LIFETIME: u64 = 6;
+const SCORE_SHARD_SIZE: f32 = 6.0;
+const ICON_MARGIN: f32 = 30.0;
+const ICON_SIZE: f32 = 32.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerUp {
+    /// Repairs the hull and protects the ship for a while
+    Shield,
+    /// Upgrades the ship weapon for a while
+    WeaponUpgrade,
+    ExtraLife,
+    /// Makes destroyed asteroids scatter score shards, pulled towards the ship
+    /// with the pickups
+    ScoreMagnet,
+    /// Slows obstacles down
+    SlowTime,
+}
+
+impl PowerUp {
+    fn sprite(self) -> u32 {
+        match self {
+            PowerUp::Shield => 0,
+            PowerUp::WeaponUpgrade => 1,
+            PowerUp::ExtraLife => 2,
+            PowerUp::ScoreMagnet => 3,
+            PowerUp::SlowTime => 4,
+        }
+    }
+
+    /// Duration of the buff, `None` for instant power-ups
+    pub fn duration(self) -> Option<f32> {
+        match self {
+            PowerUp::Shield => Some(6.0),
+            PowerUp::WeaponUpgrade => Some(12.0),
+            PowerUp::ExtraLife => None,
+            PowerUp::ScoreMagnet => Some(15.0),
+            PowerUp::SlowTime => Some(8.0),
+        }
+    }
+}
+
+/// Chance for an asteroid class to drop a power-up, and the relative weights
+/// of each power-up
+#[derive(Debug, Clone)]
+pub struct DropChance {
+    pub asteroid: AsteroidClass,
+    pub chance: f64,
+    pub power_ups: Vec<(PowerUp, u32)>,
+}
+
+pub struct DropTable(pub Vec<DropChance>);
+
+impl Default for DropTable {
+    fn default() -> Self {
+        DropTable(vec![
+            DropChance {
+                asteroid: AsteroidClass::Small,
+                chance: 0.04,
+                power_ups: vec![
+                    (PowerUp::Shield, 3),
+                    (PowerUp::WeaponUpgrade, 3),
+                    (PowerUp::ScoreMagnet, 2),
+                    (PowerUp::SlowTime, 2),
+                ],
+            },
+            DropChance {
+                asteroid: AsteroidClass::Big,
+                chance: 0.08,
+                power_ups: vec![
+                    (PowerUp::Shield, 3),
+                    (PowerUp::WeaponUpgrade, 3),
+                    (PowerUp::ExtraLife, 1),
+                    (PowerUp::ScoreMagnet, 2),
+                    (PowerUp::SlowTime, 2),
+                ],
+            },
+            DropChance {
+                asteroid: AsteroidClass::Saucer,
+                chance: 0.5,
+                power_ups: vec![
+                    (PowerUp::WeaponUpgrade, 2),
+                    (PowerUp::ExtraLife, 1),
+                    (PowerUp::SlowTime, 1),
+                ],
+            },
+            DropChance {
+                asteroid: AsteroidClass::SmallSaucer,
+                chance: 0.75,
+                power_ups: vec![
+                    (PowerUp::Shield, 1),
+                    (PowerUp::WeaponUpgrade, 2),
+                    (PowerUp::ExtraLife, 2),
+                ],
+            },
+        ])
+    }
+}
+
+impl DropTable {
+    /// Rolls the table for a destroyed asteroid
+    fn roll(&self, asteroid: AsteroidClass, rng: &mut impl Rng) -> Option<PowerUp> {
+        let drop = self.0.iter().find(|drop| drop.asteroid == asteroid)?;
+        if !rng.gen_bool(drop.chance.clamp(0.0, 1.0)) {
+            return None;
+        }
+
+        drop.power_ups
+            .choose_weighted(rng, |(_, weight)| *weight)
+            .ok()
+            .map(|(power_up, _)| *power_up)
+    }
+}
+
+pub struct Pickup(pub PowerUp);
+
+/// Points collected by flying the ship through the shard
+pub struct ScoreShard(pub u64);
+
+/// Marks the ship while the shield power-up lasts
+pub struct Shielded;
+
+struct Buff {
+    power_up: PowerUp,
+    timer: Timer,
+}
+
+/// Timed power-ups currently active
+#[derive(Default)]
+pub struct Buffs(Vec<Buff>);
+
+impl Buffs {
+    pub fn is_active(&self, power_up: PowerUp) -> bool {
+        self.0.iter().any(|buff| buff.power_up == power_up)
+    }
+
+    /// Ratio of the remaining time of a buff
+    pub fn remaining(&self, power_up: PowerUp) -> f32 {
+        self.0
+            .iter()
+            .find(|buff| buff.power_up == power_up)
+            .map_or(0.0, |buff| 1.0 - buff.timer.percent())
+    }
+}
+
+/// Weapon of the ship before it was upgraded
+struct UpgradedWeapon(Weapon);
+
+struct BuffIcon(PowerUp);
+struct BuffGauge(PowerUp);
+
+struct PowerUpTexture(Handle<TextureAtlas>);
+struct GaugeColor(Handle<ColorMaterial>);
+struct ScoreShardColor(Handle<ColorMaterial>);
+
+fn drop_power_ups(
+    mut commands: Commands,
+    mut events: EventReader<AsteroidDestroyed>,
+    table: Res<DropTable>,
+    texture: Res<PowerUpTexture>,
+) {
+    let mut rng = thread_rng();
+    for event in events.iter() {
+        if let Some(power_up) = table.roll(event.asteroid, &mut rng) {
+            let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+            let velocity =
+                event.velocity * 0.3 + Vec2::new(angle.cos(), angle.sin()) * PICKUP_SPEED;
+
+            commands
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: texture.0.clone(),
+                    transform: Transform::from_translation(event.position.extend(5.0)),
+                    sprite: TextureAtlasSprite {
+                        index: power_up.sprite(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(Pickup(power_up))
+                .insert(Velocity::new(velocity, 0.0))
+                .insert(Wrap::from_duration(Duration::from_secs(PICKUP_LIFETIME)))
+                .insert(Collider2D {
+                    shape: Shape2D::Circle(ICON_SIZE / 2.0),
+                    ..Default::default()
+                })
+                .insert(CollisionLayer(PICKUP))
+                .insert(CollisionMask(PLAYER));
+        }
+    }
+}
+
+/// While the score magnet is active, each destroyed asteroid scatters shards
+/// worth half its points each
+fn drop_score_shards(
+    mut commands: Commands,
+    mut events: EventReader<AsteroidDestroyed>,
+    buffs: Res<Buffs>,
+    color: Res<ScoreShardColor>,
+) {
+    let mut rng = thread_rng();
+    for event in events.iter() {
+        if !buffs.is_active(PowerUp::ScoreMagnet) {
+            continue;
+        }
+
+        let points = (u64::from(event.asteroid as u16) / 2).max(1);
+        for _ in 0..SCORE_SHARDS {
+            let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+            let velocity =
+                event.velocity * 0.3 + Vec2::new(angle.cos(), angle.sin()) * PICKUP_SPEED * 2.0;
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: color.0.clone(),
+                    transform: Transform::from_translation(event.position.extend(5.0)),
+                    sprite: Sprite::new(Vec2::new(SCORE_SHARD_SIZE, SCORE_SHARD_SIZE)),
+                    ..Default::default()
+                })
+                .insert(ScoreShard(points))
+                .insert(Velocity::new(velocity, 0.0))
+                .insert(Wrap::from_duration(Duration::from_secs(
+                    SCORE_SHARD_LIFETIME,
+                )))
+                .insert(Collider2D {
+                    shape: Shape2D::Circle(SCORE_SHARD_SIZE),
+                    ..Default::default()
+                })
+                .insert(CollisionLayer(PICKUP))
+                .insert(CollisionMask(PLAYER));
+        }
+    }
+}
+
+/// Adds the points of the shards touched by the ship
+fn collect_score_shards(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    mut score: ResMut<Score>,
+    q_shards: Query<&ScoreShard>,
+    q_ghosts: Query<&Ghost>,
+    q_player: Query<(), With<PlayerControlled>>,
+) {
+    // A shard may touch both the ship and one of its ghosts
+    let mut already_done = HashSet::new();
+
+    for collision in events.iter() {
+        let shard = match q_shards.get(collision.source) {
+            Ok(shard) if !already_done.contains(&collision.source) => shard,
+            _ => continue,
+        };
+        let target = q_ghosts
+            .get(collision.target)
+            .map_or(collision.target, |ghost| ghost.target);
+        if q_player.get(target).is_err() {
+            continue;
+        }
+
+        commands.entity(collision.source).despawn();
+        already_done.insert(collision.source);
+        score.add(shard.0);
+    }
+}
+
+/// Applies the effect of a pickup touched by the ship
+fn collect_pickups(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    mut buffs: ResMut<Buffs>,
+    mut time_scale: ResMut<TimeScale>,
+    mut lifes: Option<ResMut<PlayerLifes>>,
+    bonus_lifes: Res<BonusLifes>,
+    mut life_gained: EventWriter<LifeGained>,
+    audio: Res<Audio>,
+    fx: Res<SoundEffects>,
+    audio_channels: Res<AudioChannels>,
+    q_pickups: Query<&Pickup>,
+    q_ghosts: Query<&Ghost>,
+    mut q_player: Query<
+        (Option<&mut Health>, &mut Weapon, Option<&UpgradedWeapon>),
+        With<PlayerControlled>,
+    >,
+) {
+    // A pickup may touch both the ship and one of its ghosts
+    let mut already_done = HashSet::new();
+
+    for collision in events.iter() {
+        let pickup = match q_pickups.get(collision.source) {
+            Ok(pickup) if !already_done.contains(&collision.source) => pickup,
+            _ => continue,
+        };
+        let target = q_ghosts
+            .get(collision.target)
+            .map_or(collision.target, |ghost| ghost.target);
+        let (health, mut weapon, upgraded) = match q_player.get_mut(target) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+
+        commands.entity(collision.source).despawn();
+        already_done.insert(collision.source);
+
+        match pickup.0 {
+            PowerUp::Shield => {
+                if let Some(mut health) = health {
+                    health.current = health.max;
+                }
+                commands.entity(target).insert(Shielded);
+            }
+            PowerUp::WeaponUpgrade => {
+                if upgraded.is_none() {
+                    commands.entity(target).insert(UpgradedWeapon(*weapon));
+                    *weapon = weapon.upgraded();
+                }
+            }
+            PowerUp::ExtraLife => {
+                if let Some(lifes) = lifes.as_mut() {
+                    if lifes.0 < bonus_lifes.max {
+                        lifes.0 += 1;
+                        life_gained.send(LifeGained { lifes: lifes.0 });
+                        audio.play_in_channel(fx.life.clone(), &audio_channels.fx);
+                    }
+                }
+            }
+            PowerUp::SlowTime => time_scale.hostile = SLOW_TIME_SCALE,
+            PowerUp::ScoreMagnet => {}
+        }
+
+        // Collecting an active buff restarts it
+        if let Some(duration) = pickup.0.duration() {
+            buffs.0.retain(|buff| buff.power_up != pickup.0);
+            buffs.0.push(Buff {
+                power_up: pickup.0,
+                timer: Timer::from_seconds(duration, false),
+            });
+        }
+    }
+}
+
+fn end_buff(
+    commands: &mut Commands,
+    power_up: PowerUp,
+    time_scale: &mut TimeScale,
+    q_player: &mut Query<(Entity, &mut Weapon, &UpgradedWeapon), With<PlayerControlled>>,
+    q_shielded: &Query<Entity, (With<PlayerControlled>, With<Shielded>)>,
+) {
+    match power_up {
+        PowerUp::Shield => {
+            for e in q_shielded.iter() {
+                commands.entity(e).remove::<Shielded>();
+            }
+        }
+        PowerUp::WeaponUpgrade => {
+            for (e, mut weapon, upgraded) in q_player.iter_mut() {
+                *weapon = upgraded.0;
+                commands.entity(e).remove::<UpgradedWeapon>();
+            }
+        }
+        PowerUp::SlowTime => time_scale.hostile = 1.0,
+        _ => {}
+    }
+}
+
+fn expire_buffs(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut buffs: ResMut<Buffs>,
+    mut time_scale: ResMut<TimeScale>,
+    removed: RemovedComponents<PlayerControlled>,
+    mut q_player: Query<(Entity, &mut Weapon, &UpgradedWeapon), With<PlayerControlled>>,
+    q_shielded: Query<Entity, (With<PlayerControlled>, With<Shielded>)>,
+) {
+    // Buffs are lost with the ship
+    let destroyed = removed.iter().next().is_some();
+
+    for buff in buffs.0.iter_mut() {
+        buff.timer.tick(time.delta());
+    }
+    let ended: Vec<_> = buffs
+        .0
+        .iter()
+        .filter(|buff| destroyed || buff.timer.finished())
+        .map(|buff| buff.power_up)
+        .collect();
+    buffs.0.retain(|buff| !ended.contains(&buff.power_up));
+
+    for power_up in ended {
+        end_buff(
+            &mut commands,
+            power_up,
+            &mut time_scale,
+            &mut q_player,
+            &q_shielded,
+        );
+    }
+}
+
+/// While the score magnet is active, pickups and score shards are pulled
+/// towards the ship, across the screen edges
+fn attract_pickups(
+    time: Res<Time>,
+    buffs: Res<Buffs>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_player: Query<&Transform, With<PlayerControlled>>,
+    mut q_pickups: Query<(&Transform, &mut Velocity), Or<(With<Pickup>, With<ScoreShard>)>>,
+) {
+    if !buffs.is_active(PowerUp::ScoreMagnet) {
+        return;
+    }
+
+    if let (Ok(projection), Ok(player)) = (q_projection.single(), q_player.single()) {
+        for (transform, mut velocity) in q_pickups.iter_mut() {
+            let offset = wrapped_offset(
+                transform.translation.truncate(),
+                player.translation.truncate(),
+                projection,
+            );
+            if offset.length_squared() > 1.0 {
+                velocity.translation += offset.normalize() * MAGNET_PULL * time.delta_seconds();
+            }
+        }
+    }
+}
+
+/// Keeps one icon and gauge per active buff, lined up in the top right corner
+fn display_buffs(
+    mut commands: Commands,
+    buffs: Res<Buffs>,
+    texture: Res<PowerUpTexture>,
+    gauge_color: Res<GaugeColor>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    mut q_icons: Query<(Entity, &BuffIcon, &mut Transform)>,
+    mut q_gauges: Query<(Entity, &BuffGauge, &mut Transform, &mut Sprite)>,
+) {
+    let projection = match q_projection.single() {
+        Ok(projection) => projection,
+        Err(_) => return,
+    };
+    let position = |index: usize| {
+        Vec3::new(
+            projection.right - ICON_MARGIN - index as f32 * (ICON_SIZE + ICON_MARGIN / 2.0),
+            projection.top - ICON_MARGIN,
+            20.0,
+        )
+    };
+
+    for (e, icon, mut transform) in q_icons.iter_mut() {
+        match buffs.0.iter().position(|buff| buff.power_up == icon.0) {
+            Some(index) => transform.translation = position(index),
+            None => commands.entity(e).despawn(),
+        }
+    }
+
+    for (e, gauge, mut transform, mut sprite) in q_gauges.iter_mut() {
+        match buffs.0.iter().position(|buff| buff.power_up == gauge.0) {
+            Some(index) => {
+                let width = ICON_SIZE * buffs.remaining(gauge.0);
+                transform.translation =
+                    position(index) - Vec3::new((ICON_SIZE - width) / 2.0, ICON_SIZE * 0.7, 0.0);
+                sprite.size = Vec2::new(width, 3.0);
+            }
+            None => commands.entity(e).despawn(),
+        }
+    }
+
+    for (index, buff) in buffs.0.iter().enumerate() {
+        if q_icons
+            .iter_mut()
+            .all(|(_, icon, _)| icon.0 != buff.power_up)
+        {
+            commands
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: texture.0.clone(),
+                    transform: Transform::from_translation(position(index)),
+                    sprite: TextureAtlasSprite {
+                        index: buff.power_up.sprite(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(BuffIcon(buff.power_up))
+                .insert(NoWrapProtection);
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: gauge_color.0.clone(),
+                    transform: Transform::from_translation(
+                        position(index) - Vec3::new(0.0, ICON_SIZE * 0.7, 0.0),
+                    ),
+                    sprite: Sprite::new(Vec2::new(ICON_SIZE, 3.0)),
+                    ..Default::default()
+                })
+                .insert(BuffGauge(buff.power_up))
+                .insert(NoWrapProtection);
+        }
+    }
+}
+
+fn prepare_resources(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(PowerUpTexture(texture_atlases.add(
+        TextureAtlas::from_grid(
+            asset_server.load("sprites/powerups.png"),
+            Vec2::new(ICON_SIZE, ICON_SIZE),
+            5,
+            1,
+        ),
+    )));
+    commands.insert_resource(GaugeColor(materials.add(Color::rgb(0.9, 0.9, 0.9).into())));
+    commands.insert_resource(ScoreShardColor(
+        materials.add(Color::rgb(1.0, 0.85, 0.3).into()),
+    ));
+}
+
+fn exit(
+    mut commands: Commands,
+    mut buffs: ResMut<Buffs>,
+    mut time_scale: ResMut<TimeScale>,
+    query: Query<
+        Entity,
+        Or<(
+            With<Pickup>,
+            With<ScoreShard>,
+            With<BuffIcon>,
+            With<BuffGauge>,
+        )>,
+    >,
+) {
+    buffs.0.clear();
+    time_scale.hostile = 1.0;
+
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+pub struct PowerUpsPlugin;
+
+impl Plugin for PowerUpsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<DropTable>()
+            .init_resource::<Buffs>()
+            .add_startup_system(prepare_resources.system())
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(exit.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(drop_power_ups.system())
+                    .with_system(collect_pickups.system())
+                    .with_system(drop_score_shards.system())
+                    .with_system(collect_score_shards.system())
+                    .with_system(expire_buffs.system())
+                    .with_system(attract_pickups.system())
+                    .with_system(display_buffs.system()),
+            );
+    }
+}
@@ -1,16 +1,62 @@
-use crate::AppState;
+use crate::{AppState, AudioChannels, GameMode, Score, SoundEffects};
 use bevy::{
-    app::{AppBuilder, Plugin},
+    app::{AppBuilder, EventWriter, Plugin},
     ecs::{
-        schedule::{State, SystemSet},
+        schedule::SystemSet,
         system::{Commands, IntoSystem, Res, ResMut},
     },
 };
+use bevy_kira_audio::Audio;
 
 pub const PLAYER_LIFES_MAX: u8 = 3;
 
 pub struct PlayerLifes(pub u8);
 
+/// Emitted each time the player is awarded an extra life
+pub struct LifeGained {
+    pub lifes: u8,
+}
+
+/// Bonus lifes rules.
+/// A life is awarded every `every` points (if set) and for each score listed
+/// in `milestones`, as long as the player has less than `max` lifes.
+pub struct BonusLifes {
+    pub every: Option<u64>,
+    pub milestones: Vec<u64>,
+    pub max: u8,
+}
+
+impl Default for BonusLifes {
+    fn default() -> Self {
+        BonusLifes {
+            every: Some(10_000),
+            milestones: Vec::new(),
+            max: 5,
+        }
+    }
+}
+
+/// Score at which bonus lifes were last checked
+#[derive(Default)]
+struct BonusLifesProgress(u64);
+
+impl BonusLifes {
+    /// Number of bonus lifes earned when the score goes from `from` to `to`
+    fn earned(&self, from: u64, to: u64) -> usize {
+        let every = match self.every {
+            Some(every) if every > 0 => (to / every - from / every) as usize,
+            _ => 0,
+        };
+        let milestones = self
+            .milestones
+            .iter()
+            .filter(|&&milestone| milestone > from && milestone <= to)
+            .count();
+
+        every + milestones
+    }
+}
+
 impl Default for PlayerLifes {
     fn default() -> Self {
         PlayerLifes(PLAYER_LIFES_MAX)
@@ -19,23 +65,49 @@ impl Default for PlayerLifes {
 
 pub struct RulesPlugin;
 
-pub fn initialize_lifes(mut commands: Commands) {
-    commands.insert_resource(PlayerLifes::default());
+pub fn initialize_lifes(mut commands: Commands, mode: Res<GameMode>) {
+    commands.insert_resource(PlayerLifes(mode.lifes()));
+    commands.insert_resource(BonusLifesProgress::default());
 }
 
 pub fn remove_lifes(mut commands: Commands) {
     commands.remove_resource::<PlayerLifes>();
+    commands.remove_resource::<BonusLifesProgress>();
 }
 
-fn game_over(mut state: ResMut<State<AppState>>, lifes: Res<PlayerLifes>) {
-    if lifes.0 == 0 {
-        state.pop().unwrap();
+fn award_bonus_lifes(
+    mut events: EventWriter<LifeGained>,
+    mut lifes: ResMut<PlayerLifes>,
+    mut progress: ResMut<BonusLifesProgress>,
+    rules: Res<BonusLifes>,
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    audio: Res<Audio>,
+    fx: Res<SoundEffects>,
+    audio_channels: Res<AudioChannels>,
+) {
+    if score.current <= progress.0 {
+        progress.0 = score.current;
+        return;
     }
+
+    for _ in 0..rules.earned(progress.0, score.current) {
+        if mode.bonus_lifes() && lifes.0 > 0 && lifes.0 < rules.max {
+            lifes.0 += 1;
+            events.send(LifeGained { lifes: lifes.0 });
+            audio.play_in_channel(fx.life.clone(), &audio_channels.fx);
+        }
+    }
+    progress.0 = score.current;
 }
 
 impl Plugin for RulesPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(game_over.system()))
+        app.init_resource::<BonusLifes>()
+            .add_event::<LifeGained>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Game).with_system(award_bonus_lifes.system()),
+            )
             .add_system_set(
                 SystemSet::on_enter(AppState::Game).with_system(initialize_lifes.system()),
             )
@@ -0,0 +1,204 @@
+/// Saucers
+/// Saucers fire enemy bullets on the `ENEMY_AMMO` layer, which only hit the
+/// ship. The large saucer shoots in random directions while the small one
+/// aims at the ship, across the screen edges, more precisely as the score
+/// grows.
+///
+/// Their movement is driven by `Steering` behaviors: the large saucer
+/// zig-zags and runs away once damaged, the small one hunts the ship for
+/// `SMALL_SAUCER_HUNT` seconds, then leaves through the nearest edge.
+use crate::{
+    wrapped_offset, Aim, AppState, AsteroidClass, Behavior, Firing, Health, Lifetime, Palette,
+    PlayerControlled, Score, Spread, Steering, Weapon, WrapCamera, ENEMY_AMMO, PLAYER,
+};
+use bevy::{
+    app::{AppBuilder, Plugin},
+    ecs::{
+        entity::Entity,
+        query::{Added, With},
+        schedule::SystemSet,
+        system::{Commands, IntoSystem, Query, Res},
+    },
+    render::camera::OrthographicProjection,
+    transform::components::Transform,
+};
+use rand::prelude::*;
+use std::time::Duration;
+
+const SMALL_SAUCER_HUNT: f32 = 12.0;
+
+/// Saucer difficulty, growing with the score until `expert_score`
+pub struct SaucerRules {
+    /// Chance for a saucer to be a small one, at the start of a game
+    pub small_chance: f64,
+    /// Chance for a saucer to be a small one, from `expert_score` on
+    pub small_chance_max: f64,
+    /// Aiming error of small saucers (radians), at the start of a game
+    pub max_aim_error: f32,
+    /// Score from which small saucers never miss
+    pub expert_score: u64,
+}
+
+impl Default for SaucerRules {
+    fn default() -> Self {
+        SaucerRules {
+            small_chance: 0.2,
+            small_chance_max: 0.8,
+            max_aim_error: 0.5,
+            expert_score: 20_000,
+        }
+    }
+}
+
+impl SaucerRules {
+    fn progress(&self, score: u64) -> f32 {
+        if self.expert_score == 0 {
+            1.0
+        } else {
+            (score as f32 / self.expert_score as f32).min(1.0)
+        }
+    }
+
+    pub fn small_saucer_chance(&self, score: u64) -> f64 {
+        let chance = self.small_chance
+            + (self.small_chance_max - self.small_chance) * self.progress(score) as f64;
+        chance.clamp(0.0, 1.0)
+    }
+
+    pub fn aim_error(&self, score: u64) -> f32 {
+        self.max_aim_error * (1.0 - self.progress(score))
+    }
+}
+
+enum Gunner {
+    Random,
+    Aimed,
+}
+
+fn saucer_weapon(asteroid: AsteroidClass) -> Weapon {
+    Weapon {
+        spread: Spread::Random(0.0),
+        speed: 350.0,
+        lifetime: Lifetime::Distance(650.0),
+        palette: Palette::Plasma,
+        cooldown: Duration::from_millis(if asteroid == AsteroidClass::SmallSaucer {
+            900
+        } else {
+            1200
+        }),
+        ..Default::default()
+    }
+    .with_layers(ENEMY_AMMO, PLAYER)
+}
+
+/// Movement of each saucer variant
+fn saucer_steering(asteroid: AsteroidClass) -> Steering {
+    match asteroid {
+        AsteroidClass::SmallSaucer => Steering::new(
+            320.0,
+            600.0,
+            vec![
+                Behavior::Cruise { weight: 0.5 },
+                Behavior::ZigZag {
+                    width: 120.0,
+                    period: 1.2,
+                    weight: 1.0,
+                },
+                Behavior::Pursue { weight: 0.4 },
+                Behavior::Evade {
+                    radius: 120.0,
+                    weight: 2.0,
+                },
+            ],
+        )
+        .with_lifetime(SMALL_SAUCER_HUNT),
+        _ => Steering::new(
+            300.0,
+            400.0,
+            vec![
+                Behavior::ZigZag {
+                    width: 200.0,
+                    period: 2.5,
+                    weight: 1.0,
+                },
+                Behavior::Evade {
+                    radius: 150.0,
+                    weight: 1.5,
+                },
+                Behavior::FleeWhenDamaged { weight: 2.0 },
+            ],
+        ),
+    }
+}
+
+/// Hit points of each saucer variant
+fn saucer_health(asteroid: AsteroidClass) -> Health {
+    match asteroid {
+        AsteroidClass::SmallSaucer => Health::new(1),
+        _ => Health::new(2),
+    }
+}
+
+fn arm_saucers(
+    mut commands: Commands,
+    query: Query<(Entity, &AsteroidClass), Added<AsteroidClass>>,
+) {
+    for (e, asteroid) in query.iter() {
+        if asteroid.is_saucer() {
+            commands
+                .entity(e)
+                .insert(saucer_weapon(*asteroid))
+                .insert(Aim(0.0))
+                .insert(saucer_steering(*asteroid))
+                .insert(saucer_health(*asteroid))
+                .insert(Firing)
+                .insert(if *asteroid == AsteroidClass::SmallSaucer {
+                    Gunner::Aimed
+                } else {
+                    Gunner::Random
+                });
+        }
+    }
+}
+
+fn aim_saucers(
+    score: Res<Score>,
+    rules: Res<SaucerRules>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_player: Query<&Transform, With<PlayerControlled>>,
+    mut q_saucers: Query<(&Gunner, &Transform, &mut Aim)>,
+) {
+    let mut rng = thread_rng();
+    let target = match (q_projection.single(), q_player.single()) {
+        (Ok(projection), Ok(player)) => Some((projection, player.translation.truncate())),
+        _ => None,
+    };
+    let error = rules.aim_error(score.current);
+
+    for (gunner, transform, mut aim) in q_saucers.iter_mut() {
+        aim.0 = match (gunner, target) {
+            (Gunner::Aimed, Some((projection, player))) => {
+                let offset = wrapped_offset(transform.translation.truncate(), player, projection);
+                let angle = offset.y.atan2(offset.x);
+                if error > 0.0 {
+                    angle + rng.gen_range(-error..error)
+                } else {
+                    angle
+                }
+            }
+            _ => rng.gen_range(0.0..std::f32::consts::PI * 2.0),
+        };
+    }
+}
+
+pub struct SaucerPlugin;
+
+impl Plugin for SaucerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SaucerRules>().add_system_set(
+            SystemSet::on_update(AppState::Game)
+                .with_system(arm_saucers.system())
+                .with_system(aim_saucers.system()),
+        );
+    }
+}
@@ -1,7 +1,12 @@
-use crate::{AppState, GameFont};
+use crate::{
+    AppState, AsteroidDestroyed, BossDefeated, GameFont, GameMode, HighScores, PlayerControlled,
+    PlayerHit, ShotMissed,
+};
 use bevy::{
-    app::{AppBuilder, Plugin},
+    app::{AppBuilder, EventReader, Plugin},
+    core::{Time, Timer},
     ecs::{
+        entity::Entity,
         query::With,
         schedule::SystemSet,
         system::{Commands, IntoSystem, Query, Res, ResMut},
@@ -19,15 +24,25 @@ pub const SCORE_BIG_ASTEROID: u16 = 5;
 pub const SCORE_SMALL_ASTEROID: u16 = 10;
 pub const SCORE_TINY_ASTEROID: u16 = 15;
 pub const SCORE_SAUCER: u16 = 100;
+pub const SCORE_SMALL_SAUCER: u16 = 250;
+pub const SCORE_BOSS_POD: u16 = 500;
+pub const SCORE_BOSS_CORE: u16 = 2000;
+pub const SCORE_GIANT_ROCK: u16 = 2500;
 
 #[derive(Default)]
 struct ScoreCounter {
     _highscore: bool,
 }
 
+/// Combo multiplier shown while playing
+struct ComboCounter;
+
 impl Score {
-    pub fn add(&mut self, increment: u16) -> bool {
-        self.current += increment;
+    /// Adds points, multiplied by the current combo multiplier
+    pub fn add(&mut self, increment: u64) -> bool {
+        self.current = self
+            .current
+            .saturating_add(increment.saturating_mul(self.multiplier as u64));
         if self.current > self.highest {
             self.highest = self.current;
             true
@@ -43,18 +58,111 @@ impl Score {
 
 pub struct ScorePlugin;
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct Score {
-    pub current: u16,
-    pub highest: u16,
+    pub current: u64,
+    pub highest: u64,
+    /// Combo multiplier, times the game mode factor, applied to the points
+    pub multiplier: u32,
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Score {
+            current: 0,
+            highest: 0,
+            multiplier: 1,
+        }
+    }
+}
+
+/// Combo rules.
+/// Kills following each other within `window` seconds build a chain, the
+/// multiplier growing by one every `kills_per_step` kills up to `max`.
+/// Each missed shot takes `miss_penalty` kills off the chain, getting hit
+/// breaks it.
+pub struct ComboRules {
+    pub window: f32,
+    pub kills_per_step: u32,
+    pub max: u32,
+    pub miss_penalty: u32,
+}
+
+impl Default for ComboRules {
+    fn default() -> Self {
+        ComboRules {
+            window: 2.0,
+            kills_per_step: 4,
+            max: 8,
+            miss_penalty: 2,
+        }
+    }
+}
+
+impl ComboRules {
+    fn multiplier(&self, chain: u32) -> u32 {
+        (1 + chain / self.kills_per_step.max(1)).min(self.max.max(1))
+    }
+}
+
+/// Current chain of kills, and time left to extend it
+struct Combo {
+    chain: u32,
+    timer: Timer,
 }
 
 pub fn startup(mut commands: Commands) {
     commands.insert_resource(Score::default());
 }
 
-pub fn reset_score(mut score: ResMut<Score>) {
+/// Starts from zero, the highest score being the best of the game mode
+pub fn reset_score(
+    mut commands: Commands,
+    rules: Res<ComboRules>,
+    mode: Res<GameMode>,
+    high_scores: Res<HighScores>,
+    mut score: ResMut<Score>,
+) {
     score.current = 0;
+    score.highest = high_scores.best(*mode);
+    score.multiplier = 1;
+    commands.insert_resource(Combo {
+        chain: 0,
+        timer: Timer::from_seconds(rules.window, false),
+    });
+}
+
+fn update_combo(
+    time: Res<Time>,
+    rules: Res<ComboRules>,
+    mode: Res<GameMode>,
+    mut combo: ResMut<Combo>,
+    mut score: ResMut<Score>,
+    mut destroyed: EventReader<AsteroidDestroyed>,
+    mut bosses: EventReader<BossDefeated>,
+    mut missed: EventReader<ShotMissed>,
+    mut hits: EventReader<PlayerHit>,
+    q_player: Query<(), With<PlayerControlled>>,
+) {
+    let kills = destroyed.iter().count() + bosses.iter().count();
+    if kills > 0 {
+        combo.chain = combo.chain.saturating_add(kills as u32);
+        combo.timer.reset();
+    } else if combo.timer.tick(time.delta()).finished() {
+        combo.chain = 0;
+    }
+
+    // Only the shots of the player count
+    for missed in missed.iter() {
+        if q_player.get(missed.shooter).is_ok() {
+            combo.chain = combo.chain.saturating_sub(rules.miss_penalty);
+        }
+    }
+    if hits.iter().next().is_some() {
+        combo.chain = 0;
+    }
+
+    score.multiplier = rules.multiplier(combo.chain) * mode.score_factor();
 }
 
 fn update_score_counter(score: Res<Score>, mut q: Query<&mut Text, With<ScoreCounter>>) {
@@ -63,6 +171,16 @@ fn update_score_counter(score: Res<Score>, mut q: Query<&mut Text, With<ScoreCou
     }
 }
 
+fn update_combo_counter(score: Res<Score>, mut q: Query<&mut Text, With<ComboCounter>>) {
+    for mut label in q.iter_mut() {
+        label.sections[0].value = if score.multiplier > 1 {
+            format!("x{}", score.multiplier)
+        } else {
+            String::new()
+        };
+    }
+}
+
 fn display_score_counter(mut q_score_counters: Query<&mut Visible, With<ScoreCounter>>) {
     for mut visible in q_score_counters.iter_mut() {
         visible.is_visible = true;
@@ -103,14 +221,56 @@ fn add_score_counter(mut commands: Commands, font: Res<GameFont>) {
         .insert(ScoreCounter::default());
 }
 
+fn add_combo_counter(mut commands: Commands, font: Res<GameFont>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    right: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: 40.0,
+                    color: Color::ORANGE,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(ComboCounter);
+}
+
+fn remove_combo_counter(mut commands: Commands, query: Query<Entity, With<ComboCounter>>) {
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(startup.system())
+        app.init_resource::<ComboRules>()
+            .add_startup_system(startup.system())
             .add_system_set(
                 SystemSet::on_enter(AppState::Game)
                     .with_system(add_score_counter.system())
+                    .with_system(add_combo_counter.system())
                     .with_system(reset_score.system()),
             )
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(update_combo.system())
+                    .with_system(update_combo_counter.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Game).with_system(remove_combo_counter.system()),
+            )
             .add_system_set(
                 SystemSet::on_pause(AppState::Game).with_system(display_score_counter.system()),
             )
@@ -120,3 +280,77 @@ impl Plugin for ScorePlugin {
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BossKind;
+    use bevy::{
+        app::Events,
+        ecs::{
+            schedule::{Stage, SystemStage},
+            world::World,
+        },
+    };
+
+    fn world_with_player() -> (World, Entity) {
+        let mut world = World::default();
+        world.insert_resource(Time::default());
+        world.insert_resource(ComboRules::default());
+        world.insert_resource(GameMode::Classic);
+        world.insert_resource(Score::default());
+        world.insert_resource(Combo {
+            chain: 0,
+            timer: Timer::from_seconds(ComboRules::default().window, false),
+        });
+        world.insert_resource(Events::<AsteroidDestroyed>::default());
+        world.insert_resource(Events::<BossDefeated>::default());
+        world.insert_resource(Events::<ShotMissed>::default());
+        world.insert_resource(Events::<PlayerHit>::default());
+        let player = world.spawn().insert(PlayerControlled).id();
+        (world, player)
+    }
+
+    /// Multiplier once `kills` are scored, then the multiplier after `then`
+    fn multipliers(kills: usize, then: impl Fn(&mut World, Entity)) -> (u32, u32) {
+        let (mut world, player) = world_with_player();
+        let mut stage = SystemStage::parallel();
+        stage.add_system(update_combo.system());
+
+        let mut bosses = world.get_resource_mut::<Events<BossDefeated>>().unwrap();
+        for _ in 0..kills {
+            bosses.send(BossDefeated {
+                kind: BossKind::GiantRock,
+            });
+        }
+        stage.run(&mut world);
+        let before = world.get_resource::<Score>().unwrap().multiplier;
+
+        then(&mut world, player);
+        stage.run(&mut world);
+        (before, world.get_resource::<Score>().unwrap().multiplier)
+    }
+
+    #[test]
+    fn missed_shots_shorten_the_chain() {
+        let (before, after) = multipliers(8, |world, player| {
+            let mut missed = world.get_resource_mut::<Events<ShotMissed>>().unwrap();
+            missed.send(ShotMissed { shooter: player });
+            missed.send(ShotMissed { shooter: player });
+        });
+        assert_eq!(before, 3);
+        assert_eq!(after, 2);
+    }
+
+    #[test]
+    fn getting_hit_resets_the_multiplier() {
+        let (before, after) = multipliers(8, |world, player| {
+            world
+                .get_resource_mut::<Events<PlayerHit>>()
+                .unwrap()
+                .send(PlayerHit { ship: player });
+        });
+        assert_eq!(before, 3);
+        assert_eq!(after, 1);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{AppState, DamageModel, GameFont, GameMode, SpawnMode, Thrust, Weapon};
+use bevy::{
+    app::{AppBuilder, Plugin},
+    ecs::{
+        entity::Entity,
+        query::With,
+        schedule::{State, SystemSet},
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, Input},
+    math::Rect,
+    render::color::Color,
+    text::{Text, TextSection, TextStyle},
+    ui::{entity::TextBundle, AlignSelf, PositionType, Style, Val},
+};
+
+/// Handling and armament of a ship archetype
+#[derive(Debug, Clone)]
+pub struct ShipClass {
+    pub name: &'static str,
+    pub thrust: Thrust,
+    pub friction: f32,
+    pub collider_radius: f32,
+    pub weapon: Weapon,
+    /// Row of the ship classes atlas holding this class frames, the original
+    /// ship atlas being used when unset
+    pub sprite_row: Option<u32>,
+    /// Optional tint of the frames
+    pub color: Option<Color>,
+}
+
+/// Ship classes the player can pick from
+pub struct ShipClasses(pub Vec<ShipClass>);
+
+impl Default for ShipClasses {
+    fn default() -> Self {
+        ShipClasses(vec![
+            ShipClass {
+                name: "Interceptor",
+                thrust: Thrust {
+                    forward: 1400.0,
+                    backward: 400.0,
+                    yaw: 22.0,
+                },
+                friction: 1.2,
+                collider_radius: 26.0,
+                weapon: Weapon {
+                    speed: 600.0,
+                    ..Weapon::burst_laser()
+                },
+                sprite_row: Some(0),
+                color: Some(Color::rgb(0.7, 0.9, 1.0)),
+            },
+            ShipClass {
+                name: "Gunship",
+                thrust: Thrust::default(),
+                friction: 1.0,
+                collider_radius: 32.0,
+                weapon: Weapon::rapid_fire(),
+                sprite_row: Some(1),
+                color: None,
+            },
+            ShipClass {
+                name: "Heavy",
+                thrust: Thrust {
+                    forward: 800.0,
+                    backward: 300.0,
+                    yaw: 12.0,
+                },
+                friction: 0.8,
+                collider_radius: 36.0,
+                weapon: Weapon::spread_shot(),
+                sprite_row: Some(2),
+                color: Some(Color::rgb(1.0, 0.75, 0.65)),
+            },
+            ShipClass {
+                name: "Classic",
+                thrust: Thrust::default(),
+                friction: 1.0,
+                collider_radius: 32.0,
+                weapon: Weapon::classic(),
+                sprite_row: None,
+                color: Some(Color::rgb(0.85, 0.85, 0.85)),
+            },
+        ])
+    }
+}
+
+/// Index of the chosen class within `ShipClasses`
+#[derive(Default)]
+pub struct SelectedShip(pub usize);
+
+impl SelectedShip {
+    pub fn class<'a>(&self, classes: &'a ShipClasses) -> &'a ShipClass {
+        &classes.0[self.0 % classes.0.len()]
+    }
+}
+
+struct ShipSelect;
+
+fn describe(class: &ShipClass) -> String {
+    format!(
+        "thrust {}  yaw {}  fire rate {}/s\n",
+        class.thrust.forward,
+        class.thrust.yaw,
+        1000 * class.weapon.burst as u128 / class.weapon.cooldown.as_millis().max(1)
+    )
+}
+
+fn describe_mode(game_mode: GameMode, mode: SpawnMode) -> String {
+    if game_mode.spawn_mode().is_some() {
+        format!("{}: {}\n", game_mode.name(), mode.name())
+    } else {
+        format!("{}: {} (Up/Down)\n", game_mode.name(), mode.name())
+    }
+}
+
+fn describe_damage(damage_model: DamageModel) -> String {
+    format!("Damage: {} (H)\n", damage_model.name())
+}
+
+fn add_ship_select(
+    mut commands: Commands,
+    font: Res<GameFont>,
+    classes: Res<ShipClasses>,
+    selected: Res<SelectedShip>,
+    game_mode: Res<GameMode>,
+    mode: Res<SpawnMode>,
+    damage_model: Res<DamageModel>,
+) {
+    let class = selected.class(&classes);
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(30.),
+                    bottom: Val::Percent(40.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: format!("< {} >\n", class.name),
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 90.,
+                            color: Color::ORANGE_RED,
+                        },
+                    },
+                    TextSection {
+                        value: describe(class),
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 32.,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: describe_mode(*game_mode, *mode),
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 32.,
+                            color: Color::ORANGE,
+                        },
+                    },
+                    TextSection {
+                        value: describe_damage(*damage_model),
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 32.,
+                            color: Color::ORANGE,
+                        },
+                    },
+                    TextSection {
+                        value: "Press Space to launch".into(),
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 42.,
+                            color: Color::BLUE,
+                        },
+                    },
+                ],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        })
+        .insert(ShipSelect);
+}
+
+fn select_ship(
+    keyboard: Res<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    classes: Res<ShipClasses>,
+    mut selected: ResMut<SelectedShip>,
+    game_mode: Res<GameMode>,
+    mut mode: ResMut<SpawnMode>,
+    mut damage_model: ResMut<DamageModel>,
+    mut q_text: Query<&mut Text, With<ShipSelect>>,
+) {
+    let count = classes.0.len();
+    if keyboard.just_pressed(KeyCode::Left) {
+        selected.0 = (selected.0 + count - 1) % count;
+    }
+    if keyboard.just_pressed(KeyCode::Right) {
+        selected.0 = (selected.0 + 1) % count;
+    }
+
+    if game_mode.spawn_mode().is_none()
+        && (keyboard.just_pressed(KeyCode::Up) || keyboard.just_pressed(KeyCode::Down))
+    {
+        *mode = mode.next();
+    }
+
+    if keyboard.just_pressed(KeyCode::H) {
+        *damage_model = damage_model.toggled();
+    }
+
+    let class = selected.class(&classes);
+    for mut text in q_text.iter_mut() {
+        text.sections[0].value = format!("< {} >\n", class.name);
+        text.sections[1].value = describe(class);
+        text.sections[2].value = describe_mode(*game_mode, *mode);
+        text.sections[3].value = describe_damage(*damage_model);
+    }
+
+    // The game replaces the selection screen so that it pops back to the title
+    if keyboard.just_pressed(KeyCode::Space) {
+        state.set(AppState::Game).unwrap();
+    } else if keyboard.just_pressed(KeyCode::Escape) {
+        state.pop().unwrap();
+    }
+}
+
+fn remove_ship_select(mut commands: Commands, query: Query<Entity, With<ShipSelect>>) {
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+pub struct ShipsPlugin;
+
+impl Plugin for ShipsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ShipClasses>()
+            .init_resource::<SelectedShip>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::ShipSelect).with_system(add_ship_select.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::ShipSelect).with_system(select_ship.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::ShipSelect).with_system(remove_ship_select.system()),
+            );
+    }
+}
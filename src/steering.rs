@@ -0,0 +1,253 @@
+/// Steering behaviors
+/// An entity with a `Steering` component is driven by a list of weighted
+/// `Behavior`s. Each behavior computes a desired velocity; the steering force
+/// is the weighted sum of the differences between the desired velocities and
+/// the current one, limited to `max_force`.
+///
+/// Distances to obstacles are measured across the screen edges, and so are
+/// distances to the player for entities which wrap themselves. New enemy
+/// types only need a new list of behaviors.
+///
+/// A `lifetime` can be given: once over, the entity only cruises and evades
+/// towards the nearest screen edge, so that it eventually leaves.
+use crate::{
+    wrapped_offset, AppState, AsteroidClass, Health, PlayerControlled, Velocity, Wrap, WrapCamera,
+};
+use bevy::{
+    app::{AppBuilder, Plugin},
+    core::Time,
+    ecs::{
+        query::{With, Without},
+        schedule::SystemSet,
+        system::{IntoSystem, Query, Res},
+    },
+    math::Vec2,
+    render::camera::OrthographicProjection,
+    transform::components::Transform,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Behavior {
+    /// Keeps going in the initial direction
+    Cruise { weight: f32 },
+    /// Switches between two lanes `width` pixels apart every `period` seconds
+    ZigZag {
+        width: f32,
+        period: f32,
+        weight: f32,
+    },
+    /// Heads where the player is going to be
+    Pursue { weight: f32 },
+    /// Steers away from asteroids closer than `radius`
+    Evade { radius: f32, weight: f32 },
+    /// Runs away from the player once `Health` is not full
+    FleeWhenDamaged { weight: f32 },
+}
+
+pub struct Steering {
+    pub behaviors: Vec<Behavior>,
+    pub max_speed: f32,
+    pub max_force: f32,
+    /// Seconds before the entity heads for the nearest screen edge
+    pub lifetime: Option<f32>,
+    heading: Option<Vec2>,
+    elapsed: f32,
+}
+
+impl Steering {
+    pub fn new(max_speed: f32, max_force: f32, behaviors: Vec<Behavior>) -> Self {
+        Steering {
+            behaviors,
+            max_speed,
+            max_force,
+            lifetime: None,
+            heading: None,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn with_lifetime(mut self, lifetime: f32) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    /// Drops every behavior but cruising and evading, cruising towards
+    /// `direction`
+    fn leave(&mut self, direction: Vec2) {
+        self.heading = Some(direction);
+        self.behaviors
+            .retain(|behavior| matches!(behavior, Behavior::Evade { .. }));
+        self.behaviors.push(Behavior::Cruise { weight: 1.0 });
+    }
+}
+
+/// Direction of the screen edge closest to `position`
+fn nearest_edge(position: Vec2, projection: &OrthographicProjection) -> Vec2 {
+    let edges = [
+        (position.x - projection.left, -Vec2::X),
+        (projection.right - position.x, Vec2::X),
+        (position.y - projection.bottom, -Vec2::Y),
+        (projection.top - position.y, Vec2::Y),
+    ];
+    edges
+        .iter()
+        .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map_or(Vec2::X, |(_, direction)| *direction)
+}
+
+/// What a steering entity knows about its surroundings
+struct Surroundings<'a> {
+    position: Vec2,
+    velocity: Vec2,
+    player: Option<(Vec2, Vec2)>,
+    obstacles: &'a [Vec2],
+    damaged: bool,
+    /// Whether the entity goes across the screen edges
+    wraps: bool,
+    projection: &'a OrthographicProjection,
+}
+
+impl Surroundings<'_> {
+    /// Offset from the entity to the player, across the screen edges only if
+    /// the entity can follow it there
+    fn to_player(&self, player: Vec2) -> Vec2 {
+        if self.wraps {
+            wrapped_offset(self.position, player, self.projection)
+        } else {
+            player - self.position
+        }
+    }
+}
+
+impl Steering {
+    /// Desired velocity and weight of a behavior, if it applies
+    fn desired(&self, behavior: &Behavior, around: &Surroundings) -> Option<(Vec2, f32)> {
+        let heading = self.heading.unwrap_or(Vec2::X);
+        match *behavior {
+            Behavior::Cruise { weight } => Some((heading * self.max_speed, weight)),
+            Behavior::ZigZag {
+                width,
+                period,
+                weight,
+            } => {
+                let half_period = (period / 2.0).max(0.01);
+                let side = if (self.elapsed / (half_period * 2.0)).fract() < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                };
+                let lateral = heading.perp() * side * width / half_period;
+                Some((heading * self.max_speed + lateral, weight))
+            }
+            Behavior::Pursue { weight } => {
+                let (player, player_velocity) = around.player?;
+                let offset = around.to_player(player);
+                let lookahead = offset.length() / self.max_speed.max(1.0);
+                let offset = offset + player_velocity * lookahead;
+                Some((offset.normalize_or_zero() * self.max_speed, weight))
+            }
+            Behavior::Evade { radius, weight } => {
+                let away = around
+                    .obstacles
+                    .iter()
+                    .map(|obstacle| wrapped_offset(*obstacle, around.position, around.projection))
+                    .filter(|offset| offset.length_squared() < radius * radius)
+                    .fold(Vec2::ZERO, |away, offset| {
+                        away + offset.normalize_or_zero() * (1.0 - offset.length() / radius)
+                    });
+                if away == Vec2::ZERO {
+                    None
+                } else {
+                    Some((away.normalize() * self.max_speed, weight))
+                }
+            }
+            Behavior::FleeWhenDamaged { weight } => {
+                if !around.damaged {
+                    return None;
+                }
+                let (player, _) = around.player?;
+                let offset = -around.to_player(player);
+                Some((offset.normalize_or_zero() * self.max_speed, weight))
+            }
+        }
+    }
+
+    fn force(&self, around: &Surroundings) -> Vec2 {
+        let force = self
+            .behaviors
+            .iter()
+            .filter_map(|behavior| self.desired(behavior, around))
+            .fold(Vec2::ZERO, |force, (desired, weight)| {
+                force + (desired - around.velocity) * weight
+            });
+
+        if force.length() > self.max_force {
+            force.normalize() * self.max_force
+        } else {
+            force
+        }
+    }
+}
+
+fn steer(
+    time: Res<Time>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_player: Query<(&Transform, &Velocity), With<PlayerControlled>>,
+    q_obstacles: Query<(&Transform, &AsteroidClass), Without<Steering>>,
+    mut query: Query<(
+        &mut Steering,
+        &Transform,
+        &mut Velocity,
+        Option<&Health>,
+        Option<&Wrap>,
+    )>,
+) {
+    let projection = match q_projection.single() {
+        Ok(projection) => projection,
+        Err(_) => return,
+    };
+    let player = q_player
+        .single()
+        .ok()
+        .map(|(transform, velocity)| (transform.translation.truncate(), velocity.translation));
+    let obstacles: Vec<Vec2> = q_obstacles
+        .iter()
+        .filter(|(_, asteroid)| !asteroid.is_saucer())
+        .map(|(transform, _)| transform.translation.truncate())
+        .collect();
+    let delta_time = f32::min(0.2, time.delta_seconds());
+
+    for (mut steering, transform, mut velocity, health, wrap) in query.iter_mut() {
+        if steering.heading.is_none() {
+            steering.heading = Some(velocity.translation.normalize_or_zero());
+        }
+        steering.elapsed += delta_time;
+        if matches!(steering.lifetime, Some(lifetime) if steering.elapsed >= lifetime) {
+            steering.lifetime = None;
+            steering.leave(nearest_edge(transform.translation.truncate(), projection));
+        }
+
+        let around = Surroundings {
+            position: transform.translation.truncate(),
+            velocity: velocity.translation,
+            player,
+            obstacles: &obstacles,
+            damaged: matches!(health, Some(health) if health.current < health.max),
+            wraps: wrap.is_some(),
+            projection,
+        };
+
+        velocity.translation += steering.force(&around) * delta_time;
+        if velocity.translation.length() > steering.max_speed {
+            velocity.translation = velocity.translation.normalize() * steering.max_speed;
+        }
+    }
+}
+
+pub struct SteeringPlugin;
+
+impl Plugin for SteeringPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(steer.system()));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{
+    Acceleration, AppState, AudioChannels, Emission, ParticleEmitter, ParticleLabel, Pool,
+    SoundEffects, Thrust, Velocity, Volumes, Wrapped,
+};
+use bevy::{
+    app::{AppBuilder, Plugin},
+    ecs::{
+        entity::Entity,
+        query::{Added, With, Without},
+        schedule::{ParallelSystemDescriptorCoercion, SystemSet},
+        system::{Commands, IntoSystem, Query, RemovedComponents, Res, ResMut},
+    },
+    math::Vec2,
+    render::color::Color,
+    transform::components::Transform,
+};
+use bevy_kira_audio::Audio;
+use std::collections::HashMap;
+
+const EXHAUST_SPEED: f32 = 250.0;
+const EXHAUST_SPREAD: f32 = 0.25;
+const EXHAUST_LIFETIME: f32 = 0.3;
+const EXHAUST_COLORS: [Color; 4] = [
+    Color::rgb(1.0, 0.91, 0.6),
+    Color::rgb(1.0, 0.7, 0.2),
+    Color::rgb(1.0, 0.45, 0.1),
+    Color::rgb(0.6, 0.7, 1.0),
+];
+
+/// Exhaust emitter placed `offset` pixels behind the entity center.
+/// It emits up to `rate` particles per second, proportionally to the forward
+/// acceleration.
+pub struct Thruster {
+    pub rate: f32,
+    pub offset: f32,
+}
+
+impl Default for Thruster {
+    fn default() -> Self {
+        Thruster {
+            rate: 120.0,
+            offset: 24.0,
+        }
+    }
+}
+
+/// Particle emitters of each thruster: behind the entity, then behind each of
+/// its ghosts
+#[derive(Default)]
+struct Exhausts(HashMap<Entity, [Option<Entity>; 4]>);
+
+/// Ratio of the forward thrust currently in use
+fn throttle(acceleration: &Acceleration, thrust: &Thrust) -> f32 {
+    if thrust.forward > 0.0 {
+        (acceleration.forward / thrust.forward).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Lets the emitter die out once its particles are, handing it back to its
+/// pool
+fn stop_exhaust(q_emitters: &mut Query<(&mut ParticleEmitter, &mut Transform)>, emitter: Entity) {
+    if let Ok((mut emitter, _)) = q_emitters.get_mut(emitter) {
+        emitter.emission = Emission::Continuous {
+            rate: 0.0,
+            duration: Some(0.0),
+        };
+    }
+}
+
+/// Keeps an exhaust emitter behind each thruster, and behind its ghosts when
+/// the entity is wrapped so the flame shows on both sides of the screen.
+/// Emitters are created at the full rate, which sizes their particle pool,
+/// and throttled from then on.
+fn emit_exhaust(
+    mut commands: Commands,
+    mut pool: ResMut<Pool<ParticleEmitter>>,
+    mut exhausts: ResMut<Exhausts>,
+    q_thrusters: Query<
+        (
+            Entity,
+            &Thruster,
+            &Acceleration,
+            &Thrust,
+            &Transform,
+            &Velocity,
+            Option<&Wrapped>,
+        ),
+        Without<ParticleEmitter>,
+    >,
+    q_transforms: Query<&Transform, Without<ParticleEmitter>>,
+    mut q_emitters: Query<(&mut ParticleEmitter, &mut Transform)>,
+) {
+    let gone: Vec<Entity> = exhausts
+        .0
+        .keys()
+        .filter(|thruster| q_thrusters.get(**thruster).is_err())
+        .copied()
+        .collect();
+    for thruster in gone {
+        if let Some(emitters) = exhausts.0.remove(&thruster) {
+            for emitter in emitters.iter().flatten() {
+                stop_exhaust(&mut q_emitters, *emitter);
+            }
+        }
+    }
+
+    for (entity, thruster, acceleration, thrust, transform, velocity, wrapped) in q_thrusters.iter()
+    {
+        let rotation = transform.rotation.to_axis_angle();
+        let angle = std::f32::consts::PI * 1.5 + rotation.0.z * rotation.1;
+        let direction = Vec2::new(angle.cos(), angle.sin());
+        let rate = thruster.rate * throttle(acceleration, thrust);
+
+        let mut origins = [Some(transform.translation), None, None, None];
+        if let Some(wrapped) = wrapped {
+            for (origin, ghost) in origins[1..].iter_mut().zip(wrapped.ghosts.iter()) {
+                *origin = ghost
+                    .and_then(|ghost| q_transforms.get(ghost).ok())
+                    .map(|ghost_transform| ghost_transform.translation);
+            }
+        }
+
+        let emitters = exhausts.0.entry(entity).or_default();
+        for (emitter, origin) in emitters.iter_mut().zip(origins.iter()) {
+            let position = origin.map(|origin| origin + (direction * thruster.offset).extend(-0.1));
+            match (*emitter, position) {
+                (Some(current), Some(position)) => {
+                    if let Ok((mut exhaust, mut exhaust_transform)) = q_emitters.get_mut(current) {
+                        exhaust.emission = Emission::Continuous {
+                            rate,
+                            duration: None,
+                        };
+                        exhaust.cone = Some((direction, EXHAUST_SPREAD));
+                        exhaust.velocity = velocity.translation;
+                        exhaust_transform.translation = position;
+                    }
+                }
+                (None, Some(position)) => {
+                    let exhaust = pool.acquire(&mut commands);
+                    commands
+                        .entity(exhaust)
+                        .insert(ParticleEmitter {
+                            emission: Emission::Continuous {
+                                rate: thruster.rate,
+                                duration: None,
+                            },
+                            cone: Some((direction, EXHAUST_SPREAD)),
+                            speed: (EXHAUST_SPEED * 0.5, EXHAUST_SPEED),
+                            velocity: velocity.translation,
+                            lifetime: (EXHAUST_LIFETIME * 0.5, EXHAUST_LIFETIME),
+                            palette: EXHAUST_COLORS.to_vec(),
+                            color_over_life: vec![Color::WHITE],
+                            size: (2.0, 4.0),
+                            ..Default::default()
+                        })
+                        .insert(Transform::from_translation(position));
+                    *emitter = Some(exhaust);
+                }
+                (Some(current), None) => {
+                    stop_exhaust(&mut q_emitters, current);
+                    *emitter = None;
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+/// Plays the engine loop while a thruster exists, at a volume following the
+/// thrust input.
+fn engine_sound(
+    audio: Res<Audio>,
+    fx: Res<SoundEffects>,
+    audio_channels: Res<AudioChannels>,
+    volumes: Res<Volumes>,
+    removed: RemovedComponents<Thruster>,
+    q_new: Query<Entity, Added<Thruster>>,
+    query: Query<(&Acceleration, &Thrust), With<Thruster>>,
+) {
+    if q_new.iter().next().is_some() {
+        audio.stop_channel(&audio_channels.engine);
+        audio.play_looped_in_channel(fx.engine.clone(), &audio_channels.engine);
+    }
+
+    let volume = query
+        .iter()
+        .map(|(acceleration, thrust)| throttle(acceleration, thrust))
+        .fold(None, |max: Option<f32>, throttle| {
+            Some(max.map_or(throttle, |max| max.max(throttle)))
+        });
+
+    if let Some(volume) = volume {
+        audio.set_volume_in_channel(
+            (0.1 + volume * 0.9) * volumes.effects,
+            &audio_channels.engine,
+        );
+    } else if removed.iter().next().is_some() {
+        audio.stop_channel(&audio_channels.engine);
+    }
+}
+
+fn stop_engine_sound(audio: Res<Audio>, audio_channels: Res<AudioChannels>) {
+    audio.stop_channel(&audio_channels.engine);
+}
+
+/// Emitters are released by the particles once the game is left
+fn forget_exhausts(mut exhausts: ResMut<Exhausts>) {
+    exhausts.0.clear();
+}
+
+pub struct ThrusterPlugin;
+
+impl Plugin for ThrusterPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Exhausts>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(emit_exhaust.system().after(ParticleLabel::AttachMeshes))
+                    .with_system(engine_sound.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Game)
+                    .with_system(stop_engine_sound.system())
+                    .with_system(forget_exhausts.system()),
+            );
+    }
+}
@@ -1,9 +1,9 @@
-use crate::{AppState, GameFont, Score};
+use crate::{AppState, GameFont, GameMode, HighScores, Score};
 use bevy::{
     app::{AppBuilder, Plugin},
     ecs::{
         entity::Entity,
-        query::With,
+        query::{With, Without},
         schedule::{State, SystemSet},
         system::{Commands, IntoSystem, Query, Res, ResMut},
     },
@@ -18,9 +18,66 @@ pub struct TitlePlugin;
 
 struct Title;
 
+/// Title text showing the selected game mode
+struct ModeSelector;
+
+/// High score table of the selected game mode
+struct HighScoreTable;
+
 fn launch_game(keyboard: Res<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
     if keyboard.just_released(KeyCode::Space) {
-        state.push(AppState::Game).unwrap();
+        state.push(AppState::ShipSelect).unwrap();
+    }
+}
+
+fn mode_line(mode: GameMode) -> String {
+    format!("< {} >\n", mode.name())
+}
+
+/// Header and rows of the high score table of the mode, empty if it has no
+/// entry
+fn high_score_table(high_scores: &HighScores, mode: GameMode) -> (String, String) {
+    let table = high_scores
+        .entries(mode)
+        .enumerate()
+        .map(|(rank, entry)| {
+            format!(
+                "{:>2}. {:<3} {:>6}\n",
+                rank + 1,
+                entry.initials,
+                entry.score
+            )
+        })
+        .collect::<String>();
+    if table.is_empty() {
+        (String::new(), table)
+    } else {
+        (format!("High scores - {}\n", mode.name()), table)
+    }
+}
+
+fn select_mode(
+    keyboard: Res<Input<KeyCode>>,
+    high_scores: Res<HighScores>,
+    mut mode: ResMut<GameMode>,
+    mut q_selector: Query<&mut Text, (With<ModeSelector>, Without<HighScoreTable>)>,
+    mut q_table: Query<&mut Text, (With<HighScoreTable>, Without<ModeSelector>)>,
+) {
+    if keyboard.just_pressed(KeyCode::Left) {
+        *mode = mode.previous();
+    } else if keyboard.just_pressed(KeyCode::Right) {
+        *mode = mode.next();
+    } else {
+        return;
+    }
+
+    for mut text in q_selector.iter_mut() {
+        text.sections[1].value = mode_line(*mode);
+    }
+    let (header, table) = high_score_table(&high_scores, *mode);
+    for mut text in q_table.iter_mut() {
+        text.sections[0].value = header.clone();
+        text.sections[1].value = table.clone();
     }
 }
 
@@ -69,7 +126,54 @@ fn add_score_title(mut commands: Commands, score: Res<Score>, font: Res<GameFont
         .insert(Title);
 }
 
-fn add_title(mut commands: Commands, font: Res<GameFont>) {
+fn add_high_scores(
+    mut commands: Commands,
+    high_scores: Res<HighScores>,
+    mode: Res<GameMode>,
+    font: Res<GameFont>,
+) {
+    let (header, table) = high_score_table(&high_scores, *mode);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(50.),
+                    bottom: Val::Percent(40.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: header,
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 42.,
+                            color: Color::ORANGE_RED,
+                        },
+                    },
+                    TextSection {
+                        value: table,
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 32.,
+                            color: Color::WHITE,
+                        },
+                    },
+                ],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        })
+        .insert(Title)
+        .insert(HighScoreTable);
+}
+
+fn add_title(mut commands: Commands, mode: Res<GameMode>, font: Res<GameFont>) {
     commands
         .spawn_bundle(TextBundle {
             style: Style {
@@ -93,7 +197,15 @@ fn add_title(mut commands: Commands, font: Res<GameFont>) {
                         },
                     },
                     TextSection {
-                        value: "Press Space".into(),
+                        value: mode_line(*mode),
+                        style: TextStyle {
+                            font: font.0.clone(),
+                            font_size: 42.,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "Left/Right: mode, Space: play".into(),
                         style: TextStyle {
                             font: font.0.clone(),
                             font_size: 42.,
@@ -105,17 +217,27 @@ fn add_title(mut commands: Commands, font: Res<GameFont>) {
             },
             ..Default::default()
         })
-        .insert(Title);
+        .insert(Title)
+        .insert(ModeSelector);
 }
 
 impl Plugin for TitlePlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_state(AppState::Title)
-            .add_system_set(SystemSet::on_enter(AppState::Title).with_system(add_title.system()))
-            .add_system_set(SystemSet::on_update(AppState::Title).with_system(launch_game.system()))
+            .add_system_set(
+                SystemSet::on_enter(AppState::Title)
+                    .with_system(add_title.system())
+                    .with_system(add_high_scores.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Title)
+                    .with_system(launch_game.system())
+                    .with_system(select_mode.system()),
+            )
             .add_system_set(
                 SystemSet::on_resume(AppState::Title)
                     .with_system(add_score_title.system())
+                    .with_system(add_high_scores.system())
                     .with_system(add_title.system()),
             )
             .add_system_set(
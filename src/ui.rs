@@ -1,6 +1,8 @@
-use crate::{AppState, NoWrapProtection, PlayerLifes, PlayerTexture, WrapCamera, PLAYER_LIFES_MAX};
+use crate::{
+    AppState, GameMode, LifeGained, NoWrapProtection, PlayerLifes, PlayerTexture, WrapCamera,
+};
 use bevy::{
-    app::{AppBuilder, Plugin},
+    app::{AppBuilder, EventReader, Plugin},
     asset::{AssetServer, Handle},
     core::{Time, Timer},
     ecs::{
@@ -21,6 +23,7 @@ struct LifeToken(u8);
 struct LifeTokenAnimDelay(Timer);
 
 const TOKEN_MARGIN: f32 = 25.0;
+const TOKEN_SPRITE: u32 = 11;
 
 // For now position according to cursor
 fn position_life_tokens(
@@ -67,20 +70,77 @@ fn despawn_life_tokens(
     }
 }
 
-fn create_ui(mut commands: Commands, player_texture: Res<PlayerTexture>) {
-    for life in 0..PLAYER_LIFES_MAX {
-        commands
-            .spawn_bundle(SpriteSheetBundle {
-                texture_atlas: player_texture.0.clone(),
-                transform: Transform::from_scale(Vec3::new(0.5, 0.5, 1.0)),
-                sprite: TextureAtlasSprite {
-                    index: 11,
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(LifeToken(life))
-            .insert(NoWrapProtection);
+/// Plays the despawn animation in reverse for tokens of lifes the player has
+fn animate_new_life_tokens(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut q_tokens: Query<(
+        Entity,
+        &LifeToken,
+        &mut TextureAtlasSprite,
+        &mut LifeTokenAnimDelay,
+    )>,
+    lifes: Res<PlayerLifes>,
+) {
+    for (t, token, mut sprite, mut delay) in q_tokens.iter_mut() {
+        if token.0 < lifes.0 && delay.0.tick(time.delta()).just_finished() {
+            if sprite.index < TOKEN_SPRITE {
+                sprite.index += 1;
+            } else {
+                commands.entity(t).remove::<LifeTokenAnimDelay>();
+            }
+        }
+    }
+}
+
+fn spawn_life_token(commands: &mut Commands, player_texture: &PlayerTexture, life: u8, index: u32) {
+    let mut e = commands.spawn_bundle(SpriteSheetBundle {
+        texture_atlas: player_texture.0.clone(),
+        transform: Transform::from_scale(Vec3::new(0.5, 0.5, 1.0)),
+        sprite: TextureAtlasSprite {
+            index,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    e.insert(LifeToken(life)).insert(NoWrapProtection);
+
+    if index < TOKEN_SPRITE {
+        e.insert(LifeTokenAnimDelay(Timer::new(
+            Duration::from_millis(50),
+            true,
+        )));
+    }
+}
+
+/// Adds a token when a life is gained, unless the token being despawned
+/// for that life is still there: it will then be animated back.
+fn add_life_tokens(
+    mut commands: Commands,
+    mut events: EventReader<LifeGained>,
+    mode: Res<GameMode>,
+    player_texture: Res<PlayerTexture>,
+    q_tokens: Query<&LifeToken>,
+) {
+    for event in events.iter() {
+        if !mode.mortal() {
+            continue;
+        }
+        let life = event.lifes - 1;
+        if q_tokens.iter().all(|token| token.0 != life) {
+            spawn_life_token(&mut commands, &player_texture, life, 0);
+        }
+    }
+}
+
+/// Shows a token for each life the mode starts with, none when the ship
+/// cannot be destroyed
+fn create_ui(mut commands: Commands, mode: Res<GameMode>, player_texture: Res<PlayerTexture>) {
+    if !mode.mortal() {
+        return;
+    }
+    for life in 0..mode.lifes() {
+        spawn_life_token(&mut commands, &player_texture, life, TOKEN_SPRITE);
     }
 }
 
@@ -103,6 +163,8 @@ impl Plugin for UIPlugin {
             .add_system_set(
                 SystemSet::on_update(AppState::Game)
                     .with_system(despawn_life_tokens.system())
+                    .with_system(animate_new_life_tokens.system())
+                    .with_system(add_life_tokens.system())
                     .with_system(position_life_tokens.system()),
             )
             .add_system_set(SystemSet::on_enter(AppState::Game).with_system(create_ui.system()))
@@ -0,0 +1,332 @@
+/// Wave progression
+/// In `SpawnMode::Waves`, each wave spawns a set of big asteroids away from
+/// the ship, or a boss as defined by the `BossRules`. Once every asteroid is
+/// destroyed, a `WaveCleared` event is sent and the next wave, bigger and
+/// faster, is announced by a banner.
+///
+/// `SpawnMode::Endless` keeps the original behavior of asteroids arriving at
+/// random intervals forever.
+use crate::{
+    wrapped_offset, AppState, AsteroidClass, AsteroidSpawn, AsteroidSpawnRadius, Boss, BossRules,
+    BossSpawn, Collider2D, GameFont, PlayerControlled, Pool, SpawnSafety, Velocity, WrapCamera,
+};
+use bevy::{
+    app::{AppBuilder, EventWriter, Plugin},
+    core::{Time, Timer},
+    ecs::{
+        entity::Entity,
+        query::{Or, With},
+        schedule::SystemSet,
+        system::{Commands, IntoSystem, Query, Res, ResMut},
+    },
+    math::{Rect, Vec2},
+    render::{camera::OrthographicProjection, color::Color, draw::Visible},
+    text::{Text, TextSection, TextStyle},
+    transform::components::Transform,
+    ui::{entity::TextBundle, AlignSelf, PositionType, Style, Val},
+};
+use rand::prelude::*;
+
+const BANNER_DURATION: f32 = 2.0;
+const SPAWN_ATTEMPTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnMode {
+    Waves,
+    Endless,
+}
+
+impl SpawnMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            SpawnMode::Waves => "Waves",
+            SpawnMode::Endless => "Endless",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            SpawnMode::Waves => SpawnMode::Endless,
+            SpawnMode::Endless => SpawnMode::Waves,
+        }
+    }
+}
+
+/// Size and speed of the waves
+pub struct WaveRules {
+    pub count: u8,
+    /// Big asteroids added each wave
+    pub count_step: u8,
+    pub max_count: u8,
+    pub speed: (f32, f32),
+    /// Speed ratio added each wave
+    pub speed_step: f32,
+    /// Minimal distance between the ship and spawned asteroids
+    pub clear_radius: f32,
+}
+
+impl Default for WaveRules {
+    fn default() -> Self {
+        WaveRules {
+            count: 4,
+            count_step: 1,
+            max_count: 12,
+            speed: (50.0, 150.0),
+            speed_step: 0.1,
+            clear_radius: 250.0,
+        }
+    }
+}
+
+impl WaveRules {
+    pub fn count(&self, wave: u32) -> u8 {
+        let extra = wave.saturating_sub(1).min(u8::MAX as u32) as u8;
+        self.count
+            .saturating_add(extra.saturating_mul(self.count_step))
+            .min(self.max_count)
+    }
+
+    pub fn speed_factor(&self, wave: u32) -> f32 {
+        1.0 + self.speed_step * wave.saturating_sub(1) as f32
+    }
+}
+
+/// Current wave. The banner is shown before the wave spawns.
+pub struct Wave {
+    pub number: u32,
+    banner: Timer,
+    /// Big asteroids left to spawn once the banner is over, those without a
+    /// safe trajectory being tried again on the next frames
+    pending: Option<u8>,
+    spawned: bool,
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Wave {
+            number: 1,
+            banner: Timer::from_seconds(BANNER_DURATION, false),
+            pending: None,
+            spawned: false,
+        }
+    }
+}
+
+/// Emitted when every asteroid of a wave has been destroyed
+pub struct WaveCleared {
+    pub wave: u32,
+}
+
+struct WaveBanner;
+
+/// Random trajectory from the screen edges, away from the ship and keeping
+/// clear of it, none if no attempt gave one
+fn spawn_trajectory(
+    rng: &mut impl Rng,
+    projection: &OrthographicProjection,
+    rules: &WaveRules,
+    speed_factor: f32,
+    safety: &SpawnSafety,
+    ship: Option<(&Transform, &Velocity, &Collider2D)>,
+) -> Option<(Vec2, Vec2)> {
+    (0..SPAWN_ATTEMPTS)
+        .map(|_| {
+            let position = if rng.gen_bool(0.5) {
+                Vec2::new(
+                    rng.gen_range(projection.left..projection.right),
+                    if rng.gen_bool(0.5) {
+                        projection.bottom
+                    } else {
+                        projection.top
+                    },
+                )
+            } else {
+                Vec2::new(
+                    if rng.gen_bool(0.5) {
+                        projection.left
+                    } else {
+                        projection.right
+                    },
+                    rng.gen_range(projection.bottom..projection.top),
+                )
+            };
+
+            let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+            let speed = rng.gen_range(rules.speed.0..rules.speed.1) * speed_factor;
+            (position, Vec2::new(angle.cos(), angle.sin()) * speed)
+        })
+        .find(|&(position, velocity)| {
+            let clear = match ship {
+                Some((transform, ..)) => {
+                    let ship = transform.translation.truncate();
+                    wrapped_offset(ship, position, projection).length() >= rules.clear_radius
+                }
+                None => true,
+            };
+            clear && safety.is_safe(AsteroidClass::Big, (position, velocity), ship, projection)
+        })
+}
+
+fn progress_waves(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<SpawnMode>,
+    rules: Res<WaveRules>,
+    boss_rules: Res<BossRules>,
+    safety: Res<SpawnSafety>,
+    mut wave: ResMut<Wave>,
+    mut pool: ResMut<Pool<AsteroidClass>>,
+    mut cleared: EventWriter<WaveCleared>,
+    q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
+    q_player: Query<(&Transform, &Velocity, &Collider2D), With<PlayerControlled>>,
+    q_asteroids: Query<&AsteroidClass>,
+    q_bosses: Query<&Boss>,
+    q_spawns: Query<
+        Entity,
+        Or<(
+            With<AsteroidSpawn>,
+            With<AsteroidSpawnRadius>,
+            With<BossSpawn>,
+        )>,
+    >,
+) {
+    if *mode != SpawnMode::Waves {
+        return;
+    }
+
+    if wave.spawned {
+        // Saucers come and go, they do not hold the next wave
+        let remaining = q_asteroids
+            .iter()
+            .filter(|asteroid| !asteroid.is_saucer())
+            .count()
+            + q_bosses.iter().count()
+            + q_spawns.iter().count();
+        if remaining == 0 {
+            cleared.send(WaveCleared { wave: wave.number });
+            wave.number += 1;
+            wave.spawned = false;
+            wave.banner.reset();
+        }
+    } else {
+        if wave.pending.is_none() && wave.banner.tick(time.delta()).just_finished() {
+            // Boss waves have no other asteroid
+            wave.pending = Some(match boss_rules.boss_for(wave.number) {
+                Some(kind) => {
+                    commands.spawn().insert(BossSpawn(kind));
+                    0
+                }
+                None => rules.count(wave.number),
+            });
+        }
+        let mut pending = match wave.pending {
+            Some(pending) => pending,
+            None => return,
+        };
+        let projection = match q_projection.single() {
+            Ok(projection) => projection,
+            Err(_) => return,
+        };
+
+        let mut rng = thread_rng();
+        let ship = q_player.single().ok();
+        let factor = rules.speed_factor(wave.number);
+        while pending > 0 {
+            let (position, velocity) =
+                match spawn_trajectory(&mut rng, projection, &rules, factor, &safety, ship) {
+                    Some(trajectory) => trajectory,
+                    None => break,
+                };
+            let asteroid = pool.acquire(&mut commands);
+            commands.entity(asteroid).insert(AsteroidSpawn {
+                asteroid: AsteroidClass::Big,
+                position,
+                velocity,
+                spin: rng.gen_range(-5.0..5.0),
+                outline: None,
+                material: None,
+            });
+            pending -= 1;
+        }
+
+        if pending == 0 {
+            wave.pending = None;
+            wave.spawned = true;
+        } else {
+            wave.pending = Some(pending);
+        }
+    }
+}
+
+fn display_wave_banner(
+    mode: Res<SpawnMode>,
+    wave: Res<Wave>,
+    mut query: Query<(&mut Text, &mut Visible), With<WaveBanner>>,
+) {
+    for (mut text, mut visible) in query.iter_mut() {
+        visible.is_visible = *mode == SpawnMode::Waves && !wave.spawned;
+        let banner = format!("Wave {}", wave.number);
+        if text.sections[0].value != banner {
+            text.sections[0].value = banner;
+        }
+    }
+}
+
+fn enter(mut commands: Commands, font: Res<GameFont>) {
+    commands.insert_resource(Wave::default());
+
+    commands
+        .spawn_bundle(TextBundle {
+            visible: Visible {
+                is_visible: false,
+                ..Default::default()
+            },
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(42.),
+                    bottom: Val::Percent(70.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![TextSection {
+                    value: "Wave 1".into(),
+                    style: TextStyle {
+                        font: font.0.clone(),
+                        font_size: 60.,
+                        color: Color::ORANGE_RED,
+                    },
+                }],
+                alignment: Default::default(),
+            },
+            ..Default::default()
+        })
+        .insert(WaveBanner);
+}
+
+fn exit(mut commands: Commands, query: Query<Entity, With<WaveBanner>>) {
+    for e in query.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
+pub struct WavesPlugin;
+
+impl Plugin for WavesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(SpawnMode::Waves)
+            .init_resource::<WaveRules>()
+            .init_resource::<Wave>()
+            .add_event::<WaveCleared>()
+            .add_system_set(SystemSet::on_enter(AppState::Game).with_system(enter.system()))
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(exit.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(progress_waves.system())
+                    .with_system(display_wave_banner.system()),
+            );
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{AppState, AsteroidClass, AudioChannels, CollisionLayer, CollisionMask};
+use crate::{
+    pause::running, recycle_or_despawn, AppState, AsteroidClass, AudioChannels, CollisionLayer,
+    CollisionMask, Pooled, Recycle,
+};
 use bevy::{
     app::{AppBuilder, Plugin},
     asset::{Assets, Handle},
@@ -10,12 +13,12 @@ use bevy::{
         system::{Commands, IntoSystem, Query, Res},
     },
     math::{Quat, Vec2, Vec3},
-    render::camera::OrthographicProjection,
+    render::{camera::OrthographicProjection, color::Color, draw::Visible, mesh::Mesh},
     sprite::{
         entity::{SpriteBundle, SpriteSheetBundle},
         ColorMaterial, Sprite, TextureAtlas, TextureAtlasSprite,
     },
-    transform::components::Transform,
+    transform::{components::Transform, hierarchy::DespawnRecursiveExt},
 };
 use bevy_kira_audio::Audio;
 use std::time::Duration;
@@ -81,6 +84,7 @@ pub struct Ghost {
     rotation: Quat,
     direction: GDir,
     index: Option<u32>,
+    color: Option<Color>,
 }
 
 impl Ghost {
@@ -91,6 +95,7 @@ impl Ghost {
             shift: Default::default(),
             rotation: Default::default(),
             index: None,
+            color: None,
         }
     }
 }
@@ -168,6 +173,31 @@ impl Area {
     }
 }
 
+/// Shortest offset going from `from` to `to` within the wrapped play area.
+/// Since the screen wraps, `to` can either be reached directly or through one
+/// of the screen edges: the closest of those is returned.
+pub fn wrapped_offset(from: Vec2, to: Vec2, projection: &OrthographicProjection) -> Vec2 {
+    let size = Vec2::new(
+        projection.right - projection.left,
+        projection.top - projection.bottom,
+    );
+    let mut offset = to - from;
+
+    if offset.x > size.x / 2.0 {
+        offset.x -= size.x;
+    } else if offset.x < -size.x / 2.0 {
+        offset.x += size.x;
+    }
+
+    if offset.y > size.y / 2.0 {
+        offset.y -= size.y;
+    } else if offset.y < -size.y / 2.0 {
+        offset.y += size.y;
+    }
+
+    offset
+}
+
 /// Ghost creation function
 /// This system looks for any valid entity with the `Wrap` tag and no `Wrapped` tag yet.
 /// For each, it'll create 3 ghosts (tagged `Ghost`) that will position correctly using
@@ -181,6 +211,7 @@ pub fn spawn_ghosts_sprite(
             Entity,
             &mut Wrap,
             &Handle<ColorMaterial>,
+            &Handle<Mesh>,
             &Transform,
             &Sprite,
             Option<&CollisionMask>,
@@ -192,7 +223,7 @@ pub fn spawn_ghosts_sprite(
     if let Ok(projection) = q_projection.single() {
         let screen_rect = Area::from_projection(&projection);
 
-        for (entity, mut wrap, material, transform, sprite, collision_mask, layer_mask) in
+        for (entity, mut wrap, material, mesh, transform, sprite, collision_mask, layer_mask) in
             query.iter_mut()
         {
             let sprite_rect = Area::new(transform.translation.truncate(), sprite.size);
@@ -206,7 +237,9 @@ pub fn spawn_ghosts_sprite(
                 for direction in &[GDir::WestEast, GDir::NorthSouth, GDir::Diagonal] {
                     let mut entity_commands = commands.spawn_bundle(SpriteBundle {
                         material: material.clone(),
+                        mesh: mesh.clone(),
                         transform: transform.clone(),
+                        sprite: Sprite::new(sprite.size),
                         ..Default::default()
                     });
 
@@ -280,6 +313,7 @@ pub fn spawn_ghosts_sprite_atlas(
                             transform: transform.clone(),
                             sprite: TextureAtlasSprite {
                                 index: sprite.index,
+                                color: sprite.color,
                                 ..Default::default()
                             },
                             ..Default::default()
@@ -316,7 +350,7 @@ fn despawn_ghosts_indirect(
 ) {
     for (entity, ghost) in q_ghosts.iter() {
         if q_targets.get(ghost.target).is_err() {
-            commands.entity(entity).despawn();
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
@@ -340,7 +374,7 @@ fn despawn_ghosts_direct_sprite(
             if sprite_rect.inside(&screen_rect) {
                 for ghost in wrapped.ghosts.iter() {
                     if let Some(ghost) = ghost {
-                        commands.entity(*ghost).despawn();
+                        commands.entity(*ghost).despawn_recursive();
                     }
                 }
                 commands.entity(entity).remove::<Wrapped>();
@@ -378,7 +412,7 @@ fn despawn_ghosts_direct_sprite_atlas(
                 if sprite_rect.inside(&screen_rect) {
                     for ghost in wrapped.ghosts.iter() {
                         if let Some(ghost) = ghost {
-                            commands.entity(*ghost).despawn();
+                            commands.entity(*ghost).despawn_recursive();
                         }
                     }
                     commands.entity(entity).remove::<Wrapped>();
@@ -444,22 +478,23 @@ fn despawn_unwrapped_sprite(
     mut commands: Commands,
     q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
     mut query: Query<
-        (Entity, &Sprite, &mut Transform),
+        (Entity, &Sprite, &mut Transform, Option<&Recycle>),
         (
             Without<Wrap>,
             Without<Wrapped>,
             Without<Ghost>,
             Without<NoWrapProtection>,
+            Without<Pooled>,
         ),
     >,
 ) {
     if let Ok(projection) = q_projection.single() {
         let screen_rect = Area::from_projection(projection);
-        for (entity, sprite, transform) in query.iter_mut() {
+        for (entity, sprite, transform, recycle) in query.iter_mut() {
             let sprite_rect = Area::new(transform.translation.truncate(), sprite.size);
 
             if sprite_rect.outside(&screen_rect) {
-                commands.entity(entity).despawn();
+                recycle_or_despawn(&mut commands, entity, recycle);
             }
         }
     }
@@ -478,18 +513,20 @@ fn despawn_unwrapped_sprite_atlas(
             &mut Transform,
             &TextureAtlasSprite,
             Option<&AsteroidClass>,
+            Option<&Recycle>,
         ),
         (
             Without<Wrap>,
             Without<Wrapped>,
             Without<Ghost>,
             Without<NoWrapProtection>,
+            Without<Pooled>,
         ),
     >,
 ) {
     if let Ok(projection) = q_projection.single() {
         let screen_rect = Area::from_projection(projection);
-        for (entity, texture_atlas, transform, sprite, asteroid) in query.iter_mut() {
+        for (entity, texture_atlas, transform, sprite, asteroid, recycle) in query.iter_mut() {
             if let Some(texture_atlas) = texture_atlases.get(texture_atlas) {
                 if Area::from_position_atlas(
                     transform.translation.truncate(),
@@ -498,10 +535,10 @@ fn despawn_unwrapped_sprite_atlas(
                 )
                 .outside(&screen_rect)
                 {
-                    if matches!(asteroid, Some(AsteroidClass::Saucer)) {
+                    if matches!(asteroid, Some(asteroid) if asteroid.is_saucer()) {
                         audio.stop_channel(&audio_channels.fx_ufo);
                     }
-                    commands.entity(entity).despawn();
+                    recycle_or_despawn(&mut commands, entity, recycle);
                 }
             }
         }
@@ -510,6 +547,16 @@ fn despawn_unwrapped_sprite_atlas(
 
 struct DistanceFromScreen(f32);
 
+/// Pooled entities start afresh when acquired again
+fn forget_pooled_distances(
+    mut commands: Commands,
+    query: Query<Entity, (With<Pooled>, With<DistanceFromScreen>)>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).remove::<DistanceFromScreen>();
+    }
+}
+
 /// Edge case for a specific subset of sprite entities:
 /// - Are `Wrap` but not `Wrapped` yet
 /// - Are outside of the screen
@@ -532,13 +579,14 @@ fn teleport_wrap_non_wrapped_sprite_atlas(
             &Transform,
             &TextureAtlasSprite,
             Option<&DistanceFromScreen>,
+            Option<&Recycle>,
         ),
         (With<Wrap>, Without<Ghost>, Without<Wrapped>),
     >,
 ) {
     if let Ok(projection) = q_projection.single() {
         let screen_rect = Area::from_projection(projection);
-        for (entity, texture_atlas, transform, sprite, last_distance) in query.iter() {
+        for (entity, texture_atlas, transform, sprite, last_distance, recycle) in query.iter() {
             let position = transform.translation.truncate();
 
             if let Some(texture_atlas) = texture_atlases.get(texture_atlas) {
@@ -548,7 +596,7 @@ fn teleport_wrap_non_wrapped_sprite_atlas(
                     let distance = screen_rect.distance_squared(position);
                     if let Some(last_distance) = last_distance {
                         if distance >= last_distance.0 {
-                            commands.entity(entity).despawn();
+                            recycle_or_despawn(&mut commands, entity, recycle);
                         }
                     } else {
                         commands.entity(entity).insert(DistanceFromScreen(distance));
@@ -576,19 +624,25 @@ fn teleport_wrap_non_wrapped_sprite(
     mut commands: Commands,
     q_projection: Query<&OrthographicProjection, With<WrapCamera>>,
     query: Query<
-        (Entity, &Transform, &Sprite, Option<&DistanceFromScreen>),
+        (
+            Entity,
+            &Transform,
+            &Sprite,
+            Option<&DistanceFromScreen>,
+            Option<&Recycle>,
+        ),
         (With<Wrap>, Without<Ghost>, Without<Wrapped>),
     >,
 ) {
     if let Ok(projection) = q_projection.single() {
         let screen_rect = Area::from_projection(projection);
-        for (entity, transform, sprite, last_distance) in query.iter() {
+        for (entity, transform, sprite, last_distance, recycle) in query.iter() {
             let position = transform.translation.truncate();
             if Area::new(position, sprite.size).outside(&screen_rect) {
                 let distance = screen_rect.distance_squared(position);
                 if let Some(last_distance) = last_distance {
                     if distance >= last_distance.0 {
-                        commands.entity(entity).despawn();
+                        recycle_or_despawn(&mut commands, entity, recycle);
                     }
                 } else {
                     commands.entity(entity).insert(DistanceFromScreen(distance));
@@ -666,6 +720,7 @@ fn make_ghost_sprite_index(
     for mut ghost in q_ghosts.iter_mut() {
         if let Ok(sprite) = q_targets.get_component::<TextureAtlasSprite>(ghost.target) {
             ghost.index = Some(sprite.index);
+            ghost.color = Some(sprite.color);
         }
     }
 }
@@ -728,6 +783,23 @@ fn set_ghost_sprite_index(mut query: Query<(&mut TextureAtlasSprite, &Ghost)>) {
         if let Some(index) = ghost.index {
             sprite.index = index;
         }
+        if let Some(color) = ghost.color {
+            sprite.color = color;
+        }
+    }
+}
+
+/// Ghosts show and hide along with their target, blinking included
+fn set_ghost_visibility(
+    q_targets: Query<&Visible, Without<Ghost>>,
+    mut q_ghosts: Query<(&mut Visible, &Ghost)>,
+) {
+    for (mut visible, ghost) in q_ghosts.iter_mut() {
+        if let Ok(target) = q_targets.get(ghost.target) {
+            if visible.is_visible != target.is_visible {
+                visible.is_visible = target.is_visible;
+            }
+        }
     }
 }
 
@@ -772,14 +844,16 @@ impl Plugin for WrapPlugin {
             )
             .add_system(set_ghost_transforms.system().after(Label::Make))
             .add_system(set_ghost_sprite_index.system().after(Label::Make))
+            .add_system(set_ghost_visibility.system())
             .add_system(despawn_ghosts_indirect.system())
             .add_system(despawn_ghosts_direct_sprite.system())
             .add_system(despawn_ghosts_direct_sprite_atlas.system())
-            .add_system(auto_unwrap.system())
+            .add_system(auto_unwrap.system().with_run_criteria(running.system()))
             .add_system(remove_nowrap_protection_sprite.system())
             .add_system(remove_nowrap_protection_sprite_atlas.system())
             .add_system(despawn_unwrapped_sprite.system())
             .add_system(despawn_unwrapped_sprite_atlas.system())
+            .add_system(forget_pooled_distances.system())
             .add_system_set(
                 SystemSet::on_exit(AppState::Game).with_system(unwrap_everything.system()),
             );